@@ -1,6 +1,8 @@
 use std::time::Duration;
 
-use crate::engine::{Engine, SearchLimits};
+use crate::engine::{Engine, SearchLimits, SearchStats};
+
+pub const DEFAULT_BENCH_DEPTH: u8 = 8;
 
 const BENCH_POSITIONS: &[&str] = &[
     "r4rk1/5pb1/3R2p1/p2Q1qBp/8/7P/1P3PP1/2R3K1 w - - 4 29",
@@ -69,23 +71,31 @@ const BENCH_POSITIONS: &[&str] = &[
     "rn1qkbnr/ppp1pppp/4b3/3p4/P1PP4/8/1P2PPPP/RNBQKBNR w KQkq - 0 5",
 ];
 
-pub fn run_bench() {
+pub fn run_bench(depth: u8) {
     let mut engine = Engine::new(16 * 1024 * 1024);
     let mut total_nodes = 0;
     let mut total_time = Duration::ZERO;
+    let mut total_stats = SearchStats::default();
     for fen in BENCH_POSITIONS {
         let init_pos = fen.parse().expect("failed to parse fen");
-        let limits = SearchLimits::PerMove { depth: 8 };
+        let limits = SearchLimits::PerMove { depth };
 
         let mut nodes = 0;
         let mut time = Duration::ZERO;
+        let mut stats = SearchStats::default();
         engine.think(&init_pos, &[], limits, &mut |info| {
             nodes = info.nodes;
             time = info.time;
+            stats = info.stats;
         });
 
         total_nodes += nodes;
         total_time += time;
+        total_stats.merge(&stats);
+    }
+
+    if SearchStats::ENABLED {
+        println!("{}", total_stats);
     }
 
     let nps = (total_nodes as f32 / total_time.as_secs_f32()) as u64;
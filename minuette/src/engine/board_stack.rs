@@ -1,24 +1,39 @@
-use cozy_chess::{Board, Move};
+use cozy_chess::{get_between_rays, Board, Move, Piece, Rank, Square};
+
+use super::cuckoo;
+
+#[derive(Debug, Clone, Copy)]
+struct HistoryEntry {
+    hash: u64,
+    /// Plies since the last irreversible move or null move.
+    /// No position further back than this can repeat the current one.
+    reversible_plies: u16,
+}
 
 pub struct BoardStack {
-    history: Vec<u64>,
+    history: Vec<HistoryEntry>,
     stack: Vec<Board>,
+    root: usize,
 }
 
 impl BoardStack {
     pub fn new(init_pos: &Board, moves_played: &[Move]) -> Self {
         let mut history = Vec::with_capacity(256);
         let mut board = init_pos.clone();
+        history.push(HistoryEntry {
+            hash: repetition_hash(&board),
+            reversible_plies: board.halfmove_clock() as u16,
+        });
         for &mv in moves_played {
-            history.push(board.hash());
             board.play(mv);
+            history.push(HistoryEntry::after(history.last(), &board));
         }
-        history.push(board.hash());
 
         let mut stack = Vec::with_capacity(256);
         stack.push(board);
 
-        Self { history, stack }
+        let root = history.len() - 1;
+        Self { history, stack, root }
     }
 
     pub fn get(&self) -> &Board {
@@ -29,7 +44,7 @@ impl BoardStack {
         let mut next = self.get().clone();
         next.play_unchecked(mv);
 
-        self.history.push(next.hash());
+        self.history.push(HistoryEntry::after(self.history.last(), &next));
         self.stack.push(next);
     }
 
@@ -37,7 +52,10 @@ impl BoardStack {
         let Some(next) = self.get().null_move() else {
             return false;
         };
-        self.history.push(next.hash());
+        self.history.push(HistoryEntry {
+            hash: repetition_hash(&next),
+            reversible_plies: 0,
+        });
         self.stack.push(next);
         true
     }
@@ -47,8 +65,240 @@ impl BoardStack {
         self.stack.pop();
     }
 
-    pub fn repetitions(&self) -> usize {
-        let hash = self.get().hash();
-        self.history.iter().filter(|&&h| h == hash).count()
+    /// Whether the current position should be scored as a draw.
+    /// Checkmate and stalemate are left to [`Board::status`].
+    pub fn is_draw(&self) -> bool {
+        self.is_fifty_move_draw() || self.is_repetition()
+    }
+
+    /// Whether the fifty-move rule applies.
+    /// Checkmate on the hundredth ply takes precedence.
+    pub fn is_fifty_move_draw(&self) -> bool {
+        let board = self.get();
+        board.halfmove_clock() >= 100 && (board.checkers().is_empty() || board.generate_moves(|_| true))
+    }
+
+    /// Whether the current position repeats an earlier one.
+    /// A repetition of a position reached after the root is a draw at two-fold,
+    /// since the side that allowed it could have avoided it. Otherwise three-fold is needed.
+    pub fn is_repetition(&self) -> bool {
+        let current = self.history.last().expect("missing history?");
+        let scan_limit = current.reversible_plies as usize;
+        let current_index = self.history.len() - 1;
+
+        let mut repetitions = 0;
+        for distance in (4..=scan_limit).step_by(2) {
+            let Some(index) = current_index.checked_sub(distance) else {
+                break;
+            };
+            if self.history[index].hash != current.hash {
+                continue;
+            }
+            if index > self.root {
+                return true;
+            }
+            repetitions += 1;
+            if repetitions >= 2 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the side to move can repeat an earlier position with a single reversible move,
+    /// which makes the position at least a draw for it. Uses the cuckoo tables to find the move.
+    /// Before the root the repetition must be a three-fold one, as in [`BoardStack::is_repetition`].
+    pub fn has_upcoming_repetition(&self) -> bool {
+        let current = self.history.last().expect("missing history?");
+        let scan_limit = current.reversible_plies as usize;
+        let current_index = self.history.len() - 1;
+        let board = self.get();
+
+        for distance in (3..=scan_limit).step_by(2) {
+            let Some(index) = current_index.checked_sub(distance) else {
+                break;
+            };
+            let Some((a, b)) = cuckoo::lookup(current.hash ^ self.history[index].hash) else {
+                continue;
+            };
+            if !(get_between_rays(a, b) & board.occupied()).is_empty() {
+                continue;
+            }
+            if index > self.root {
+                return true;
+            }
+
+            let piece_square = match board.occupied().has(a) {
+                true => a,
+                false => b,
+            };
+            if board.color_on(piece_square) == Some(board.side_to_move()) && self.repeats_earlier(index) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the position at `index` in the history repeats an even earlier one.
+    fn repeats_earlier(&self, index: usize) -> bool {
+        let entry = &self.history[index];
+        (4..=entry.reversible_plies as usize)
+            .step_by(2)
+            .map_while(|distance| index.checked_sub(distance))
+            .any(|earlier| self.history[earlier].hash == entry.hash)
+    }
+}
+
+impl HistoryEntry {
+    fn after(prev: Option<&HistoryEntry>, board: &Board) -> Self {
+        let prev_plies = prev.map_or(u16::MAX, |prev| prev.reversible_plies);
+        Self {
+            hash: repetition_hash(board),
+            reversible_plies: (board.halfmove_clock() as u16).min(prev_plies.saturating_add(1)),
+        }
+    }
+}
+
+/// The position hash, ignoring an en passant file that no pawn can actually capture on,
+/// since the position is otherwise the same as far as repetitions are concerned.
+fn repetition_hash(board: &Board) -> u64 {
+    let Some(file) = board.en_passant() else {
+        return board.hash();
+    };
+
+    let ep_square = Square::new(file, Rank::Sixth.relative_to(board.side_to_move()));
+    let our_pawns = board.colored_pieces(board.side_to_move(), Piece::Pawn);
+    let mut can_capture = false;
+    board.generate_moves_for(our_pawns, |moves| {
+        can_capture |= moves.to.has(ep_square);
+        can_capture
+    });
+
+    match can_capture {
+        true => board.hash(),
+        false => board.hash_without_ep(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    fn moves(moves: &[&str]) -> Vec<Move> {
+        moves.iter().map(|mv| mv.parse().unwrap()).collect()
+    }
+
+    fn play(board: &mut BoardStack, moves: &[&str]) {
+        for &mv in moves {
+            board.play_unchecked(mv.parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn threefold_before_root() {
+        let played = [SHUFFLE, SHUFFLE].concat();
+        let board = BoardStack::new(&Board::startpos(), &moves(&played));
+        assert!(board.is_repetition());
+
+        let board = BoardStack::new(&Board::startpos(), &moves(&SHUFFLE));
+        assert!(!board.is_repetition());
+    }
+
+    #[test]
+    fn twofold_after_root() {
+        let mut board = BoardStack::new(&Board::startpos(), &moves(&["e2e4"]));
+        play(&mut board, &["e7e5"]);
+        assert!(!board.is_repetition());
+
+        play(&mut board, &SHUFFLE);
+        assert!(board.is_repetition());
+    }
+
+    #[test]
+    fn twofold_of_root_is_not_a_draw() {
+        let mut board = BoardStack::new(&Board::startpos(), &[]);
+        play(&mut board, &SHUFFLE);
+        assert!(!board.is_repetition());
+
+        play(&mut board, &SHUFFLE);
+        assert!(board.is_repetition());
+    }
+
+    #[test]
+    fn repetition_scan_stops_at_irreversible_moves() {
+        let mut board = BoardStack::new(&Board::startpos(), &moves(&SHUFFLE));
+        play(&mut board, &["e2e4", "e7e5"]);
+        play(&mut board, &SHUFFLE);
+        assert!(board.is_repetition());
+        assert_eq!(board.history.last().unwrap().reversible_plies, 4);
+    }
+
+    #[test]
+    fn repetition_scan_stops_at_null_moves() {
+        let mut board = BoardStack::new(&Board::startpos(), &moves(&["e2e4"]));
+        play(&mut board, &["e7e5", "g1f3"]);
+        assert!(board.null_move());
+        assert!(board.null_move());
+        play(&mut board, &["g8f6", "f3g1", "f6g8"]);
+        assert!(!board.is_repetition());
+    }
+
+    #[test]
+    fn upcoming_repetition_after_root() {
+        let mut board = BoardStack::new(&Board::startpos(), &moves(&["e2e4"]));
+        play(&mut board, &["e7e5", "g1f3", "g8f6"]);
+        assert!(!board.has_upcoming_repetition());
+
+        play(&mut board, &["f3g1"]);
+        assert!(board.has_upcoming_repetition());
+    }
+
+    #[test]
+    fn upcoming_repetition_blocked() {
+        // The queen returns to a4 by way of c6, but can't go straight back past the knight.
+        for (fen, reachable) in [("q3k3/8/7N/8/8/8/8/5K2 w - - 0 1", true), ("q3k3/8/N7/8/8/8/8/5K2 w - - 0 1", false)] {
+            let mut board = BoardStack::new(&fen.parse().unwrap(), &moves(&["f1e1"]));
+            play(&mut board, &["e8d8", "e1d1", "a8c6", "d1d2", "c6a4", "d2e1"]);
+            assert_eq!(board.has_upcoming_repetition(), reachable, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn upcoming_repetition_before_root() {
+        let board = BoardStack::new(&Board::startpos(), &moves(&["g1f3", "g8f6", "f3g1"]));
+        assert!(!board.has_upcoming_repetition());
+
+        let played = [&SHUFFLE[..], &["g1f3", "g8f6", "f3g1"]].concat();
+        let board = BoardStack::new(&Board::startpos(), &moves(&played));
+        assert!(board.has_upcoming_repetition());
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let board = "4k3/8/8/8/8/8/8/4K2R w - - 99 80".parse::<Board>().unwrap();
+        let mut stack = BoardStack::new(&board, &[]);
+        assert!(!stack.is_fifty_move_draw());
+
+        play(&mut stack, &["h1h2"]);
+        assert!(stack.is_fifty_move_draw());
+        assert!(stack.is_draw());
+        stack.undo();
+
+        play(&mut stack, &["e1e2"]);
+        assert!(stack.is_fifty_move_draw());
+        stack.undo();
+
+        play(&mut stack, &["h1h8"]);
+        assert!(stack.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn checkmate_beats_fifty_move_rule() {
+        let board = "k7/8/1K6/8/8/8/8/7R w - - 99 80".parse::<Board>().unwrap();
+        let mut stack = BoardStack::new(&board, &[]);
+        play(&mut stack, &["h1h8"]);
+        assert!(!stack.is_fifty_move_draw());
     }
 }
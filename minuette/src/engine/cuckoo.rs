@@ -0,0 +1,161 @@
+use std::sync::LazyLock;
+
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_rook_moves,
+    BitBoard, BoardBuilder, Color, Piece, Square,
+};
+
+const TABLE_SIZE: usize = 8192;
+
+/// Cuckoo hash tables of every reversible piece move on an empty board, keyed by the
+/// difference it makes to the position hash, as in Stockfish's `has_game_cycle`.
+/// This finds the move that connects two positions in constant time.
+struct Cuckoo {
+    keys: [u64; TABLE_SIZE],
+    moves: [Option<(Square, Square)>; TABLE_SIZE],
+}
+
+static CUCKOO: LazyLock<Box<Cuckoo>> = LazyLock::new(|| {
+    let mut cuckoo = Box::new(Cuckoo {
+        keys: [0; TABLE_SIZE],
+        moves: [None; TABLE_SIZE],
+    });
+    let side_key = side_key();
+
+    for &color in &Color::ALL {
+        for &piece in &[Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King] {
+            for &from in &Square::ALL {
+                for to in piece_moves(piece, from) {
+                    if to <= from {
+                        continue;
+                    }
+                    let key = move_key(piece, color, from, to) ^ side_key;
+                    cuckoo.insert(key, (from, to));
+                }
+            }
+        }
+    }
+    cuckoo
+});
+
+impl Cuckoo {
+    fn insert(&mut self, mut key: u64, mv: (Square, Square)) {
+        let mut mv = Some(mv);
+        let mut index = h1(key);
+        loop {
+            std::mem::swap(&mut self.keys[index], &mut key);
+            std::mem::swap(&mut self.moves[index], &mut mv);
+            if mv.is_none() {
+                break;
+            }
+            index = match index == h1(key) {
+                true => h2(key),
+                false => h1(key),
+            };
+        }
+    }
+}
+
+/// The reversible move that turns a position into one with a hash differing by `key`, if any.
+/// The squares are unordered, since the move could have been made either way.
+pub fn lookup(key: u64) -> Option<(Square, Square)> {
+    let cuckoo = &**CUCKOO;
+    [h1(key), h2(key)]
+        .into_iter()
+        .find(|&index| cuckoo.keys[index] == key)
+        .and_then(|index| cuckoo.moves[index])
+}
+
+fn h1(key: u64) -> usize {
+    key as usize % TABLE_SIZE
+}
+
+fn h2(key: u64) -> usize {
+    (key >> 16) as usize % TABLE_SIZE
+}
+
+fn piece_moves(piece: Piece, square: Square) -> BitBoard {
+    match piece {
+        Piece::Knight => get_knight_moves(square),
+        Piece::Bishop => get_bishop_moves(square, BitBoard::EMPTY),
+        Piece::Rook => get_rook_moves(square, BitBoard::EMPTY),
+        Piece::Queen => get_bishop_moves(square, BitBoard::EMPTY) | get_rook_moves(square, BitBoard::EMPTY),
+        Piece::King => get_king_moves(square),
+        Piece::Pawn => BitBoard::EMPTY,
+    }
+}
+
+// The zobrist keys aren't public, but since the position hash is a plain XOR of them,
+// the difference between the hashes of two boards recovers the keys that differ.
+
+fn side_key() -> u64 {
+    let mut builder = BoardBuilder::empty();
+    *builder.square_mut(Square::A1) = Some((Piece::King, Color::White));
+    *builder.square_mut(Square::H8) = Some((Piece::King, Color::Black));
+    let white = builder.build().expect("invalid board?");
+    builder.side_to_move = Color::Black;
+    let black = builder.build().expect("invalid board?");
+    white.hash() ^ black.hash()
+}
+
+/// The hash difference of moving `piece` between `from` and `to`, ignoring the side to move.
+fn move_key(piece: Piece, color: Color, from: Square, to: Square) -> u64 {
+    // Kings are needed for a valid board, so find squares for them that work for both boards.
+    for &white_king in &Square::ALL {
+        for &black_king in &Square::ALL {
+            let kings = [(white_king, Color::White), (black_king, Color::Black)];
+            let build = |square| {
+                let mut builder = BoardBuilder::empty();
+                for (king, king_color) in kings {
+                    if piece != Piece::King || king_color != color {
+                        *builder.square_mut(king) = Some((Piece::King, king_color));
+                    }
+                }
+                if builder.square(square).is_some() {
+                    return None;
+                }
+                *builder.square_mut(square) = Some((piece, color));
+                builder.build().ok()
+            };
+            if let (Some(before), Some(after)) = (build(from), build(to)) {
+                return before.hash() ^ after.hash();
+            }
+        }
+    }
+    unreachable!("no valid board for move key?");
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Board;
+
+    use super::*;
+
+    #[test]
+    fn keys_match_position_hashes() {
+        let board = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQK2R w KQkq - 0 1".parse::<Board>().unwrap();
+        let mut reversible = 0;
+        board.generate_moves(|moves| {
+            for mv in moves {
+                let mut after = board.clone();
+                after.play_unchecked(mv);
+                let is_reversible = moves.piece != Piece::Pawn
+                    && board.piece_on(mv.to).is_none()
+                    && Color::ALL.iter().all(|&color| after.castle_rights(color) == board.castle_rights(color));
+                if is_reversible {
+                    reversible += 1;
+                    let (a, b) = lookup(board.hash() ^ after.hash()).expect("missing reversible move?");
+                    assert!((a, b) == (mv.from, mv.to) || (b, a) == (mv.from, mv.to));
+                }
+            }
+            false
+        });
+        assert!(reversible > 20);
+    }
+
+    #[test]
+    fn table_holds_every_move() {
+        let count = CUCKOO.moves.iter().filter(|mv| mv.is_some()).count();
+        assert_eq!(count, 3668);
+    }
+}
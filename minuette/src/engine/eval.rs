@@ -1,7 +1,6 @@
 use cozy_chess::{Board, Color, Piece, Square};
 
-pub const CHECKMATE: i16 = 30_000;
-pub const INFINITY: i16 = 31_000;
+use super::score::Score;
 
 const fn s(mg: i16, eg: i16) -> i32 {
     ((eg as i32) << 16) + mg as i32
@@ -72,7 +71,7 @@ const PIECE_SQUARE_TABLES: [[i32; Square::NUM]; Piece::NUM] = [
     ],
 ];
 
-pub fn evaluate(board: &Board) -> i16 {
+pub fn evaluate(board: &Board) -> Score {
     let phased_eval = TEMPO
         + evaluate_for_color(board, board.side_to_move())
         - evaluate_for_color(board, !board.side_to_move());
@@ -80,8 +79,8 @@ pub fn evaluate(board: &Board) -> i16 {
     let mg = phased_eval as i16 as i32;
     let eg = (phased_eval + (1 << 15)) >> 16;
 
-    let phase = game_phase(&board);
-    ((mg * phase + eg * (24 - phase)) / 24) as i16
+    let phase = game_phase(board);
+    Score::new((mg * phase + eg * (24 - phase)) / 24)
 }
 
 fn evaluate_for_color(board: &Board, color: Color) -> i32 {
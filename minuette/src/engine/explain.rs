@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use cozy_chess::{Board, GameStatus, Move};
+
+use super::board_stack::BoardStack;
+use super::history_tables::HistoryTables;
+use super::params::SearchParams;
+use super::score::Score;
+use super::search::{Search, SearchLimits};
+use super::tt::{TranspositionTable, TtBound, TtEntry};
+
+const MAX_REFUTATION_PLIES: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct MoveExplanation {
+    pub mv: Move,
+    /// The score of the move from the root's point of view.
+    pub score: Score,
+    pub bound: TtBound,
+    /// The expected reply and continuation after the move.
+    pub refutation: Vec<Move>,
+}
+
+/// Scores every legal move at the root to at least `depth`, best first, along with the line
+/// that answers it. Moves already searched deep enough by an earlier search are taken
+/// from the TT where possible; the rest get an exact score from a search of their own.
+pub fn explain(
+    tt: &mut TranspositionTable,
+    history: &mut HistoryTables,
+    params: &SearchParams,
+    init_pos: &Board,
+    moves_played: &[Move],
+    depth: u8,
+) -> Vec<MoveExplanation> {
+    let mut board = init_pos.clone();
+    for &mv in moves_played {
+        board.play(mv);
+    }
+    let mut root_moves = Vec::new();
+    board.generate_moves(|moves| {
+        root_moves.extend(moves);
+        false
+    });
+
+    // The score of the best move, if an earlier search settled it.
+    let best_score = tt.load(board.hash(), 0)
+        .filter(|entry| entry.depth >= depth && matches!(entry.bound, TtBound::Exact))
+        .map(|entry| entry.score);
+
+    let mut explanations = Vec::new();
+    for mv in root_moves {
+        let mut child_moves = moves_played.to_vec();
+        child_moves.push(mv);
+        let child_stack = BoardStack::new(init_pos, &child_moves);
+        let child = child_stack.get();
+        // A search rooted at the child can't see that the child itself is a repetition or fifty-move draw.
+        let status = match child.status() {
+            GameStatus::Ongoing if child_stack.is_draw() => GameStatus::Drawn,
+            status => status,
+        };
+        let explanation = match status {
+            GameStatus::Won => MoveExplanation {
+                mv,
+                score: Score::mate_in(1),
+                bound: TtBound::Exact,
+                refutation: Vec::new(),
+            },
+            GameStatus::Drawn => MoveExplanation {
+                mv,
+                score: Score::DRAW,
+                bound: TtBound::Exact,
+                refutation: Vec::new(),
+            },
+            GameStatus::Ongoing => match tt.load(child.hash(), 1).filter(|entry| is_informative(entry, depth, best_score)) {
+                Some(entry) => MoveExplanation {
+                    mv,
+                    score: -entry.score,
+                    // A lower bound on the reply's score is an upper bound on ours.
+                    bound: match entry.bound {
+                        TtBound::Exact => TtBound::Exact,
+                        _ => TtBound::Upper,
+                    },
+                    refutation: tt_line(tt, child),
+                },
+                None => {
+                    let limits = SearchLimits::PerMove { depth };
+                    let search = Search::new(tt, history, params, limits, Duration::ZERO, None);
+                    let mut result = None;
+                    search.start(init_pos, &child_moves, &mut |info| result = Some(info));
+                    let info = result.expect("missing search result?");
+                    MoveExplanation {
+                        mv,
+                        // The child's mate scores are a ply closer than ours.
+                        score: -info.eval.to_search(1),
+                        bound: TtBound::Exact,
+                        refutation: info.pv.into_iter().take(MAX_REFUTATION_PLIES).collect(),
+                    }
+                }
+            },
+        };
+        explanations.push(explanation);
+    }
+
+    explanations.sort_by_key(|explanation| std::cmp::Reverse(explanation.score));
+    explanations
+}
+
+/// Whether a TT entry for the position after a root move explains that move well enough.
+/// Besides an exact score, an upper bound on the move's score below the best score
+/// shows why it wasn't chosen. A lower bound says nothing of the kind.
+fn is_informative(entry: &TtEntry, depth: u8, best_score: Option<Score>) -> bool {
+    entry.depth >= depth && match entry.bound {
+        TtBound::Exact => true,
+        TtBound::Lower => best_score.is_some_and(|best_score| -entry.score < best_score),
+        TtBound::Upper => false,
+    }
+}
+
+/// Follows the TT's best moves from `board`, stopping at a missing or illegal move or a repetition.
+fn tt_line(tt: &TranspositionTable, board: &Board) -> Vec<Move> {
+    let mut board = board.clone();
+    let mut seen = vec![board.hash()];
+    let mut line = Vec::new();
+    while line.len() < MAX_REFUTATION_PLIES {
+        let Some(mv) = tt.load(board.hash(), 0).and_then(|entry| entry.best_move) else {
+            break;
+        };
+        if !board.is_legal(mv) {
+            break;
+        }
+        board.play_unchecked(mv);
+        line.push(mv);
+        if seen.contains(&board.hash()) {
+            break;
+        }
+        seen.push(board.hash());
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repetition_is_a_draw() {
+        let board = Board::startpos();
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]
+            .map(|mv| mv.parse::<Move>().unwrap());
+        let mut tt = TranspositionTable::new(1024 * 1024);
+        let mut history = HistoryTables::new();
+        let params = SearchParams::default();
+        let explanations = explain(&mut tt, &mut history, &params, &board, &moves, 3);
+        let repeat = explanations.iter()
+            .find(|explanation| explanation.mv == "f6g8".parse::<Move>().unwrap())
+            .expect("missing move?");
+        assert_eq!(repeat.score, Score::DRAW);
+        assert!(matches!(repeat.bound, TtBound::Exact));
+    }
+}
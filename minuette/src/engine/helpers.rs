@@ -1,4 +1,7 @@
-use cozy_chess::{Board, Rank, Square, Piece, Move};
+use cozy_chess::{Board, File, Rank, Square, Piece, Move, Color, BitBoard};
+use cozy_chess::{get_bishop_moves, get_rook_moves, get_knight_moves, get_king_moves, get_pawn_attacks};
+
+pub const SEE_VALUES: [i16; Piece::NUM] = [100, 300, 300, 500, 900, 0];
 
 pub fn move_is_capture(board: &Board, mv: Move) -> bool {
     captured_piece(board, mv).is_some()
@@ -10,14 +13,280 @@ pub fn captured_piece(board: &Board, mv: Move) -> Option<Piece> {
         return board.piece_on(mv.to);
     }
 
+    if is_en_passant(board, mv) {
+        return Some(Piece::Pawn);
+    }
+
+    None
+}
+
+pub fn is_en_passant(board: &Board, mv: Move) -> bool {
     let is_pawn_move = board.pieces(Piece::Pawn).has(mv.from);
     let ep_square = board.en_passant().map(|file| {
         let rank = Rank::Sixth.relative_to(board.side_to_move());
         Square::new(file, rank)
     });
-    if is_pawn_move && Some(mv.to) == ep_square {
-        return Some(Piece::Pawn);
+    is_pawn_move && Some(mv.to) == ep_square
+}
+
+pub fn is_castle(board: &Board, mv: Move) -> bool {
+    board.colors(board.side_to_move()).has(mv.to)
+}
+
+/// Whether `mv` puts the opponent in check, without playing it.
+/// Covers direct checks, discovered checks, checks by the castling rook,
+/// checks by promoted pieces, and checks revealed by an en passant capture.
+pub fn gives_check(board: &Board, mv: Move) -> bool {
+    let us = board.side_to_move();
+    let king = board.king(!us);
+    let ours = board.colors(us);
+    let moved = board.piece_on(mv.from).expect("missing piece?");
+
+    let mut occupied = board.occupied() ^ mv.from.bitboard();
+    let mut diagonal = (board.pieces(Piece::Bishop) | board.pieces(Piece::Queen)) & ours & !mv.from.bitboard();
+    let mut orthogonal = (board.pieces(Piece::Rook) | board.pieces(Piece::Queen)) & ours & !mv.from.bitboard();
+
+    // Only the rook can give a direct check when castling, from next to the king's new square.
+    let (piece, to) = match is_castle(board, mv) {
+        true => {
+            let rank = Rank::First.relative_to(us);
+            let (king_file, rook_file) = match mv.to.file() > mv.from.file() {
+                true => (File::G, File::F),
+                false => (File::C, File::D),
+            };
+            occupied ^= mv.to.bitboard();
+            occupied |= Square::new(king_file, rank).bitboard();
+            orthogonal &= !mv.to.bitboard();
+            (Piece::Rook, Square::new(rook_file, rank))
+        }
+        false => (mv.promotion.unwrap_or(moved), mv.to),
+    };
+    occupied |= to.bitboard();
+    if is_en_passant(board, mv) {
+        occupied ^= Square::new(mv.to.file(), mv.from.rank()).bitboard();
     }
 
-    None
+    let direct = match piece {
+        Piece::Pawn => get_pawn_attacks(to, us).has(king),
+        Piece::Knight => get_knight_moves(to).has(king),
+        _ => false,
+    };
+    if matches!(piece, Piece::Bishop | Piece::Queen) {
+        diagonal |= to.bitboard();
+    }
+    if matches!(piece, Piece::Rook | Piece::Queen) {
+        orthogonal |= to.bitboard();
+    }
+    // Sliders check both directly and by discovery, now that the moved piece is out of the way.
+    direct
+        || !(get_bishop_moves(king, occupied) & diagonal).is_empty()
+        || !(get_rook_moves(king, occupied) & orthogonal).is_empty()
+}
+
+/// A hash of the pawn structure alone.
+pub fn pawn_hash(board: &Board) -> u64 {
+    let white_pawns = board.colored_pieces(Color::White, Piece::Pawn).0;
+    let black_pawns = board.colored_pieces(Color::Black, Piece::Pawn).0;
+    mix(white_pawns ^ mix(black_pawns))
+}
+
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+pub fn attackers_to(board: &Board, square: Square, occupied: BitBoard) -> BitBoard {
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let orthogonal = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let white_pawns = board.colored_pieces(Color::White, Piece::Pawn);
+    let black_pawns = board.colored_pieces(Color::Black, Piece::Pawn);
+
+    (get_pawn_attacks(square, Color::Black) & white_pawns)
+        | (get_pawn_attacks(square, Color::White) & black_pawns)
+        | (get_knight_moves(square) & board.pieces(Piece::Knight))
+        | (get_king_moves(square) & board.pieces(Piece::King))
+        | (get_bishop_moves(square, occupied) & diagonal)
+        | (get_rook_moves(square, occupied) & orthogonal)
+}
+
+/// Static exchange evaluation.
+/// Returns whether the exchange started by `mv` on its target square
+/// wins at least `threshold` for the side to move.
+pub fn see(board: &Board, mv: Move, threshold: i16) -> bool {
+    if is_castle(board, mv) {
+        return threshold <= 0;
+    }
+
+    let moved = board.piece_on(mv.from).expect("missing attacker?");
+    let mut next_victim = mv.promotion.unwrap_or(moved);
+
+    // Tracked in i32, since thresholds can be anywhere in the i16 range.
+    let mut balance = -(threshold as i32);
+    if let Some(victim) = captured_piece(board, mv) {
+        balance += SEE_VALUES[victim as usize] as i32;
+    }
+    if let Some(promotion) = mv.promotion {
+        balance += (SEE_VALUES[promotion as usize] - SEE_VALUES[Piece::Pawn as usize]) as i32;
+    }
+    if balance < 0 {
+        return false;
+    }
+
+    balance -= SEE_VALUES[next_victim as usize] as i32;
+    if balance >= 0 {
+        return true;
+    }
+
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let orthogonal = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    let mut occupied = board.occupied() ^ mv.from.bitboard() | mv.to.bitboard();
+    if is_en_passant(board, mv) {
+        let captured = Square::new(mv.to.file(), mv.from.rank());
+        occupied ^= captured.bitboard();
+    }
+
+    let mut attackers = attackers_to(board, mv.to, occupied) & occupied;
+    let mut color = !board.side_to_move();
+    loop {
+        let our_attackers = attackers & board.colors(color);
+        if our_attackers.is_empty() {
+            break;
+        }
+
+        let attacker = Piece::ALL
+            .into_iter()
+            .find(|&piece| !(our_attackers & board.pieces(piece)).is_empty())
+            .expect("missing attacker?");
+        let from = (our_attackers & board.pieces(attacker))
+            .next_square()
+            .expect("missing attacker?");
+        occupied ^= from.bitboard();
+
+        // Reveal x-ray attackers hidden behind the piece that just captured.
+        if matches!(attacker, Piece::Pawn | Piece::Bishop | Piece::Queen) {
+            attackers |= get_bishop_moves(mv.to, occupied) & diagonal;
+        }
+        if matches!(attacker, Piece::Rook | Piece::Queen) {
+            attackers |= get_rook_moves(mv.to, occupied) & orthogonal;
+        }
+        attackers &= occupied;
+
+        color = !color;
+        balance = -balance - 1 - SEE_VALUES[attacker as usize] as i32;
+        next_victim = attacker;
+
+        if balance >= 0 {
+            // The king can only capture if the square is no longer defended.
+            if next_victim == Piece::King && !(attackers & board.colors(color)).is_empty() {
+                color = !color;
+            }
+            break;
+        }
+    }
+
+    board.side_to_move() != color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn see_suite() {
+        const SUITE: &[(&str, &str, i16)] = &[
+            // Undefended pawn
+            ("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5", 100),
+            // Knight for pawn, with a long exchange on e5 behind it
+            ("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5", -200),
+            // Pawn takes pawn, recaptured
+            ("4R3/2r3p1/5bk1/1p1r3p/p2PR1P1/P1BK1P2/1P6/8 b - - 0 1", "h5g4", 0),
+            // Bishop takes knight, recaptured by pawn
+            ("4r1k1/5pp1/nbp4p/1p2p2q/1P2P1b1/1BP2N1P/1B2QPPK/3R4 b - - 0 1", "g4f3", 0),
+            // Pawn takes pawn, recapture loses material
+            ("2r1r1k1/pp1bppbp/3p1np1/q3P3/2P2P2/1P2B3/P1N1B1PP/2RQ1RK1 b - - 0 1", "d6e5", 100),
+            // Queen takes pawn defended by a pawn
+            ("4k3/8/3p4/4p3/8/8/8/4QK2 w - - 0 1", "e1e5", -800),
+            // Rook takes pawn defended by a rook, with an x-ray queen behind
+            ("3rk3/8/8/3p4/8/8/3R4/3QK3 w - - 0 1", "d2d5", 100),
+            // Rook takes pawn defended by two rooks
+            ("3rk3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5", -400),
+            // Quiet moves onto attacked squares
+            ("4k3/8/8/8/3p4/8/8/3NK3 w - - 0 1", "d1c3", -300),
+            ("4k3/8/8/8/3p4/8/8/3NK3 w - - 0 1", "d1b2", 0),
+            ("4k3/8/8/8/3p4/8/5P2/3NK3 w - - 0 1", "d1e3", -200),
+            ("4k3/8/2p5/8/8/4N3/8/4K3 w - - 0 1", "e3d5", -300),
+            // King can only recapture on an undefended square
+            ("8/8/4k3/3p4/8/8/8/3RK3 w - - 0 1", "d1d5", -400),
+            ("8/8/4k3/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5", 100),
+            // En passant
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+            ("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 0),
+            // Promotions
+            ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", 800),
+            ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8q", -100),
+            ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q", 1300),
+            ("1rr1k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q", 400),
+            // Castling never loses material
+            ("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1h1", 0),
+        ];
+
+        for &(fen, mv, gain) in SUITE {
+            let board = fen.parse::<Board>().unwrap();
+            let mv = mv.parse::<Move>().unwrap();
+            assert!(see(&board, mv, gain), "{} {} should win at least {}", fen, mv, gain);
+            assert!(!see(&board, mv, gain + 1), "{} {} should win at most {}", fen, mv, gain);
+        }
+    }
+
+    #[test]
+    fn see_extreme_thresholds() {
+        let board = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1".parse::<Board>().unwrap();
+        for mv in ["a7b8q", "a7a8q", "e1d2"] {
+            let mv = mv.parse::<Move>().unwrap();
+            assert!(see(&board, mv, i16::MIN));
+            assert!(!see(&board, mv, i16::MAX));
+        }
+    }
+
+    #[test]
+    fn gives_check_matches_playing_the_move() {
+        const POSITIONS: &[&str] = &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            // Castling checks with the rook
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+            // En passant discovering a check along the rank
+            "8/8/8/K2pP2q/8/8/8/7k w - d6 0 1",
+            "8/8/8/1k1pP2R/8/8/8/4K3 w - d6 0 1",
+            // Promotions
+            "3k4/1P6/8/8/8/8/8/4K3 w - - 0 1",
+            "8/1P1k4/8/8/8/8/8/4K3 w - - 0 1",
+        ];
+
+        // Every move two plies deep, to reach plenty of discovered checks.
+        fn check_moves(board: &Board, depth: u8) {
+            board.generate_moves(|moves| {
+                for mv in moves {
+                    let mut after = board.clone();
+                    after.play_unchecked(mv);
+                    let checks = !after.checkers().is_empty();
+                    assert_eq!(gives_check(board, mv), checks, "{} {}", board, mv);
+                    if depth > 1 {
+                        check_moves(&after, depth - 1);
+                    }
+                }
+                false
+            });
+        }
+
+        for fen in POSITIONS {
+            check_moves(&fen.parse::<Board>().unwrap(), 2);
+        }
+    }
 }
@@ -1,15 +1,26 @@
 use cozy_chess::{Board, Color, Move, Piece, Square};
 
+use super::helpers::{captured_piece, pawn_hash};
+use super::score::Score;
+
+const CORRECTION_SIZE: usize = 16384;
+const CORRECTION_GRAIN: i32 = 256;
+const CORRECTION_WEIGHT_SCALE: i32 = 256;
+
 pub struct HistoryTables {
     piece_to: [[[i32; Square::NUM]; Piece::NUM]; Color::NUM],
+    capture: [[[[i32; Piece::NUM]; Square::NUM]; Piece::NUM]; Color::NUM],
+    pawn_correction: Box<[[i32; CORRECTION_SIZE]; Color::NUM]>,
 }
 
 impl HistoryTables {
-    pub const MAX_HISTORY: i32 = 512;
+    pub const MAX_CORRECTION: i32 = CORRECTION_GRAIN * 64;
 
     pub fn new() -> Self {
         Self {
             piece_to: [[[0; Square::NUM]; Piece::NUM]; Color::NUM],
+            capture: [[[[0; Piece::NUM]; Square::NUM]; Piece::NUM]; Color::NUM],
+            pawn_correction: Box::new([[0; CORRECTION_SIZE]; Color::NUM]),
         }
     }
 
@@ -19,12 +30,58 @@ impl HistoryTables {
         self.piece_to[color as usize][piece as usize][mv.to as usize]
     }
 
-    pub fn update_move(&mut self, board: &Board, mv: Move, change: i32) {
+    pub fn update_move(&mut self, board: &Board, mv: Move, change: i32, max_history: i32) {
         let color = board.side_to_move();
         let piece = board.piece_on(mv.from).expect("missing piece?");
         let score = &mut self.piece_to[color as usize][piece as usize][mv.to as usize];
+        apply_change(score, change, max_history);
+    }
+
+    pub fn get_capture_score(&self, board: &Board, mv: Move) -> i32 {
+        let color = board.side_to_move();
+        let piece = board.piece_on(mv.from).expect("missing piece?");
+        let victim = captured_piece(board, mv).expect("missing victim?");
+        self.capture[color as usize][piece as usize][mv.to as usize][victim as usize]
+    }
+
+    pub fn update_capture(&mut self, board: &Board, mv: Move, change: i32, max_history: i32) {
+        let color = board.side_to_move();
+        let piece = board.piece_on(mv.from).expect("missing piece?");
+        let victim = captured_piece(board, mv).expect("missing victim?");
+        let score = &mut self.capture[color as usize][piece as usize][mv.to as usize][victim as usize];
+        apply_change(score, change, max_history);
+    }
+
+    /// Adjusts a static eval by how wrong it has been in the past for this pawn structure.
+    pub fn correct_eval(&self, board: &Board, static_eval: Score) -> Score {
+        let correction = *self.pawn_correction_entry(board) / CORRECTION_GRAIN;
+        Score::new(static_eval.get() as i32 + correction)
+    }
+
+    /// Moves the correction for this pawn structure towards `error`, the difference
+    /// between a search result and the uncorrected static eval.
+    pub fn update_correction(&mut self, board: &Board, depth: i32, error: i32) {
+        let weight = (depth * depth + 2 * depth + 1).min(128);
+        let entry = self.pawn_correction_entry_mut(board);
+
+        *entry = (*entry * (CORRECTION_WEIGHT_SCALE - weight) + error * CORRECTION_GRAIN * weight)
+            / CORRECTION_WEIGHT_SCALE;
+        *entry = (*entry).clamp(-Self::MAX_CORRECTION, Self::MAX_CORRECTION);
+    }
+
+    fn pawn_correction_entry(&self, board: &Board) -> &i32 {
+        let index = pawn_hash(board) as usize % CORRECTION_SIZE;
+        &self.pawn_correction[board.side_to_move() as usize][index]
+    }
 
-        *score += change - change.abs() * *score / Self::MAX_HISTORY;
-        *score = (*score).clamp(-Self::MAX_HISTORY, Self::MAX_HISTORY);
+    fn pawn_correction_entry_mut(&mut self, board: &Board) -> &mut i32 {
+        let index = pawn_hash(board) as usize % CORRECTION_SIZE;
+        &mut self.pawn_correction[board.side_to_move() as usize][index]
     }
 }
+
+/// Moves a history score by `change`, with less effect the closer it already is to `max_history`.
+fn apply_change(score: &mut i32, change: i32, max_history: i32) {
+    *score += change - change.abs() * *score / max_history;
+    *score = (*score).clamp(-max_history, max_history);
+}
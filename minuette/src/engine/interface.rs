@@ -1,12 +1,32 @@
+use std::time::Duration;
+
 use cozy_chess::{Board, Move};
 
 use super::tt::TranspositionTable;
 use super::search::{Search, SearchInfo, SearchLimits};
 use super::history_tables::HistoryTables;
+use super::params::SearchParams;
+use super::tree::{SearchTree, TreeDumpConfig};
+use super::mate::{find_mate, MateResult};
+use super::explain::{explain, MoveExplanation};
+use super::mcts::{MctsSearch, MctsTree};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchAlgorithm {
+    #[default]
+    AlphaBeta,
+    /// Monte Carlo tree search, keeping its tree between moves of the same game.
+    Mcts,
+}
 
 pub struct Engine {
     tt: TranspositionTable,
     history: HistoryTables,
+    params: SearchParams,
+    move_overhead: Duration,
+    tree_dump: Option<TreeDumpConfig>,
+    algorithm: SearchAlgorithm,
+    mcts_tree: MctsTree,
 }
 
 impl Engine {
@@ -14,16 +34,44 @@ impl Engine {
         Self {
             tt: TranspositionTable::new(tt_bytes),
             history: HistoryTables::new(),
+            params: SearchParams::default(),
+            move_overhead: Duration::ZERO,
+            tree_dump: None,
+            algorithm: SearchAlgorithm::default(),
+            mcts_tree: MctsTree::new(tt_bytes),
         }
     }
 
+    /// Resizes the TT, and the MCTS tree, which shares the same budget.
     pub fn resize_tt(&mut self, tt_bytes: usize) {
         self.tt = TranspositionTable::new(tt_bytes);
+        self.mcts_tree = MctsTree::new(tt_bytes);
+    }
+
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.move_overhead = move_overhead;
+    }
+
+    /// Records the search tree of every `think` and writes it out afterwards.
+    pub fn set_tree_dump(&mut self, tree_dump: Option<TreeDumpConfig>) {
+        self.tree_dump = tree_dump;
+    }
+
+    pub fn set_search_algorithm(&mut self, algorithm: SearchAlgorithm) {
+        self.algorithm = algorithm;
+        self.mcts_tree.clear();
+    }
+
+    /// Sets a search parameter by name, returning whether it exists.
+    #[cfg(feature = "tune")]
+    pub fn set_param(&mut self, name: &str, value: i32) -> bool {
+        self.params.set(name, value)
     }
 
     pub fn reset(&mut self) {
         self.tt.clear();
         self.history = HistoryTables::new();
+        self.mcts_tree.clear();
     }
 
     pub fn think(
@@ -33,7 +81,35 @@ impl Engine {
         limits: SearchLimits,
         on_iter: &mut dyn FnMut(SearchInfo),
     ) {
-        let search = Search::new(&mut self.tt, &mut self.history, limits);
+        if self.algorithm == SearchAlgorithm::Mcts {
+            let search = MctsSearch::new(&mut self.mcts_tree, limits, self.move_overhead);
+            search.start(init_pos, moves_played, on_iter);
+            return;
+        }
+
+        let mut tree = self.tree_dump.clone().map(SearchTree::new);
+        let search = Search::new(&mut self.tt, &mut self.history, &self.params, limits, self.move_overhead, tree.as_mut());
         search.start(init_pos, moves_played, on_iter);
+
+        if let Some(Err(err)) = tree.map(|tree| tree.write()) {
+            eprintln!("failed to write search tree: {}", err);
+        }
+    }
+
+    /// Looks for the shortest forced mate for the side to move within `max_moves` moves.
+    /// This answers the question exactly, unlike [`Engine::think`], but is only practical
+    /// for short mates. `max_nodes` bounds the work done for each length.
+    pub fn find_mate(&self, init_pos: &Board, moves_played: &[Move], max_moves: u16, max_nodes: usize) -> MateResult {
+        let mut board = init_pos.clone();
+        for &mv in moves_played {
+            board.play(mv);
+        }
+        find_mate(&board, max_moves, max_nodes)
+    }
+
+    /// Scores every legal move to at least `depth` with the line that refutes it, best first.
+    /// This is meant for after [`Engine::think`], whose TT entries answer most of the moves.
+    pub fn explain(&mut self, init_pos: &Board, moves_played: &[Move], depth: u8) -> Vec<MoveExplanation> {
+        explain(&mut self.tt, &mut self.history, &self.params, init_pos, moves_played, depth)
     }
 }
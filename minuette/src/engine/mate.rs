@@ -0,0 +1,246 @@
+use cozy_chess::{Board, GameStatus, Move};
+
+const INFINITE: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MateResult {
+    /// The shortest forced mate, as the full line from the root.
+    /// The defender picks the longest resistance.
+    Mate(Vec<Move>),
+    /// There is no forced mate within the requested number of moves.
+    NoMate,
+    /// The node limit ran out before the question was settled.
+    Unknown,
+}
+
+/// Nodes don't store their position, which is replayed from the root when needed.
+struct MateNode {
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// OR nodes have the attacker to move, AND nodes the defender.
+    attacker: bool,
+    ply: u16,
+    proof: u32,
+    disproof: u32,
+}
+
+/// Best-first proof-number search for a forced mate within a fixed number of plies.
+struct ProofTree {
+    root: Board,
+    nodes: Vec<MateNode>,
+    max_plies: u16,
+}
+
+/// Looks for the shortest forced mate for the side to move within `max_moves` of its moves,
+/// proving or disproving each length in turn with a proof-number search.
+/// `max_nodes` bounds the size of the proof tree for each length.
+pub fn find_mate(board: &Board, max_moves: u16, max_nodes: usize) -> MateResult {
+    let mut result = MateResult::NoMate;
+    for moves in 1..=max_moves {
+        let mut tree = ProofTree::new(board, moves * 2 - 1);
+        match tree.solve(max_nodes) {
+            Some(true) => return MateResult::Mate(tree.mating_line()),
+            Some(false) => {}
+            None => result = MateResult::Unknown,
+        }
+    }
+    result
+}
+
+impl ProofTree {
+    fn new(board: &Board, max_plies: u16) -> Self {
+        let mut tree = Self {
+            root: board.clone(),
+            nodes: Vec::new(),
+            max_plies,
+        };
+        tree.add_node(board, None, None, true, 0);
+        tree
+    }
+
+    /// Returns whether the root is proven, or `None` if the node limit was hit first.
+    fn solve(&mut self, max_nodes: usize) -> Option<bool> {
+        while self.nodes[0].proof != 0 && self.nodes[0].disproof != 0 {
+            if self.nodes.len() >= max_nodes {
+                return None;
+            }
+            let (node, board) = self.most_proving_node();
+            self.expand(node, &board);
+            self.update_ancestors(node);
+        }
+        Some(self.nodes[0].proof == 0)
+    }
+
+    fn add_node(&mut self, board: &Board, mv: Option<Move>, parent: Option<usize>, attacker: bool, ply: u16) -> usize {
+        let (proof, disproof) = match board.status() {
+            // Whoever is to move has been mated.
+            GameStatus::Won if attacker => (INFINITE, 0),
+            GameStatus::Won => (0, INFINITE),
+            GameStatus::Drawn => (INFINITE, 0),
+            // The defender survived every move the attacker had.
+            GameStatus::Ongoing if ply >= self.max_plies => (INFINITE, 0),
+            GameStatus::Ongoing if attacker => (1, 1),
+            GameStatus::Ongoing => {
+                // Every defence must be refuted, so many defences make a proof harder.
+                let mut defences = 0;
+                board.generate_moves(|moves| {
+                    defences += moves.len() as u32;
+                    false
+                });
+                (defences, 1)
+            }
+        };
+
+        self.nodes.push(MateNode {
+            mv,
+            parent,
+            children: Vec::new(),
+            attacker,
+            ply,
+            proof,
+            disproof,
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Returns the most proving node along with its position.
+    fn most_proving_node(&self) -> (usize, Board) {
+        let mut index = 0;
+        let mut board = self.root.clone();
+        while !self.nodes[index].children.is_empty() {
+            let node = &self.nodes[index];
+            index = *node.children.iter()
+                .min_by_key(|&&child| match node.attacker {
+                    true => self.nodes[child].proof,
+                    false => self.nodes[child].disproof,
+                })
+                .expect("missing child?");
+            board.play_unchecked(self.nodes[index].mv.expect("missing move?"));
+        }
+        (index, board)
+    }
+
+    fn expand(&mut self, index: usize, board: &Board) {
+        let attacker = self.nodes[index].attacker;
+        let ply = self.nodes[index].ply;
+
+        let mut moves = Vec::new();
+        board.generate_moves(|piece_moves| {
+            moves.extend(piece_moves);
+            false
+        });
+        for mv in moves {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            let child = self.add_node(&child, Some(mv), Some(index), !attacker, ply + 1);
+            self.nodes[index].children.push(child);
+        }
+    }
+
+    fn update_ancestors(&mut self, mut index: usize) {
+        loop {
+            let node = &self.nodes[index];
+            let children = node.children.iter().map(|&child| &self.nodes[child]);
+            let (proof, disproof) = match node.attacker {
+                true => (
+                    children.clone().map(|child| child.proof).min().unwrap_or(INFINITE),
+                    children.fold(0, |sum: u32, child| sum.saturating_add(child.disproof)),
+                ),
+                false => (
+                    children.clone().fold(0, |sum: u32, child| sum.saturating_add(child.proof)),
+                    children.map(|child| child.disproof).min().unwrap_or(INFINITE),
+                ),
+            };
+
+            let node = &mut self.nodes[index];
+            node.proof = proof;
+            node.disproof = disproof;
+            match node.parent {
+                Some(parent) => index = parent,
+                None => break,
+            }
+        }
+    }
+
+    /// The number of plies until mate from a proven node, with the attacker
+    /// choosing the fastest proven move and the defender the slowest.
+    fn mate_plies(&self, index: usize) -> (u16, Option<usize>) {
+        let node = &self.nodes[index];
+        let proven = node.children.iter().filter(|&&child| self.nodes[child].proof == 0);
+        let lengths = proven.map(|&child| (self.mate_plies(child).0 + 1, Some(child)));
+        let best = match node.attacker {
+            true => lengths.min_by_key(|&(plies, _)| plies),
+            false => lengths.max_by_key(|&(plies, _)| plies),
+        };
+        best.unwrap_or((0, None))
+    }
+
+    fn mating_line(&self) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut index = 0;
+        while let (_, Some(child)) = self.mate_plies(index) {
+            line.push(self.nodes[child].mv.expect("missing move?"));
+            index = child;
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_NODES: usize = 1_000_000;
+
+    fn mate_length(fen: &str, max_moves: u16) -> Option<usize> {
+        let board = fen.parse::<Board>().unwrap();
+        match find_mate(&board, max_moves, MAX_NODES) {
+            MateResult::Mate(line) => {
+                let mut end = board.clone();
+                for &mv in &line {
+                    end.try_play(mv).unwrap_or_else(|_| panic!("illegal move {} in {}", mv, fen));
+                }
+                assert_eq!(end.status(), GameStatus::Won, "line doesn't mate in {}", fen);
+                assert_eq!(line.len() % 2, 1);
+                Some(line.len().div_ceil(2))
+            }
+            MateResult::NoMate => None,
+            MateResult::Unknown => panic!("ran out of nodes in {}", fen),
+        }
+    }
+
+    #[test]
+    fn mate_suite() {
+        const SUITE: &[(&str, usize)] = &[
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 1),
+            ("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3", 1),
+            ("8/8/8/8/8/5K2/5Q2/7k w - - 0 1", 1),
+            ("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 2),
+            // Légal's mate.
+            ("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 2),
+            // Morphy's opera game.
+            ("4kb1r/p2n1ppp/4q3/4p1B1/4P3/1Q6/PPP2PPP/2KR4 w k - 1 17", 2),
+            ("6k1/pp4p1/2p5/2bp4/8/P5Pb/1P3rrP/2BRRN1K b - - 0 1", 2),
+            ("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1", 3),
+            ("2r3k1/p4p2/3Rp2p/1p2P1pK/8/1P4P1/P3Q2P/1q6 b - - 0 1", 3),
+            ("8/8/8/8/4k3/8/8/R3K2R w - - 0 1", 5),
+        ];
+        for &(fen, moves) in SUITE {
+            assert_eq!(mate_length(fen, 5), Some(moves), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn no_mate() {
+        assert_eq!(mate_length("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 2), None);
+        assert_eq!(mate_length("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 1), None);
+        assert_eq!(mate_length("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1", 2), None);
+        assert_eq!(mate_length("7k/8/6K1/8/8/8/8/R7 b - - 0 1", 3), None);
+    }
+
+    #[test]
+    fn stalemate_is_not_mate() {
+        assert_eq!(mate_length("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3), None);
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use cozy_chess::{Board, GameStatus, Move};
+
+use super::board_stack::BoardStack;
+use super::eval::evaluate;
+use super::root_moves::RootMove;
+use super::score::Score;
+use super::search::{SearchInfo, SearchLimits};
+use super::stats::SearchStats;
+use super::time_manager::TimeManager;
+
+/// Centipawns per unit of log-odds when turning evals into win probabilities.
+const EVAL_SCALE: f32 = 400.0;
+/// Centipawns per unit of log-odds in the policy, from the evals of each move's position.
+const POLICY_TEMPERATURE: f32 = 100.0;
+const EXPLORATION: f32 = 1.5;
+/// Unvisited moves are assumed to be this much worse than their parent.
+const FPU_REDUCTION: f32 = 0.2;
+/// The most legal moves in any position, and so the most nodes one expansion adds.
+const MAX_MOVES: usize = 218;
+/// Enough that pruning to half the tree always leaves room for the root's children and more.
+const MIN_TREE_NODES: usize = 16 * MAX_MOVES;
+/// Depth limits are on the average depth, which can stall, so fixed depth searches also stop here.
+const MAX_FIXED_DEPTH_PLAYOUTS: u64 = 1 << 20;
+const CHECK_INTERVAL: u64 = 256;
+
+#[derive(Debug, Clone, Copy)]
+struct MctsNode {
+    mv: Option<Move>,
+    first_child: u32,
+    child_count: u8,
+    expanded: bool,
+    /// The result for the side to move if the game is over here.
+    terminal: Option<f32>,
+    prior: f32,
+    visits: u32,
+    /// The sum of results for the side that played `mv`, so that a parent picks the child with the highest mean.
+    value_sum: f32,
+}
+
+impl MctsNode {
+    fn new(mv: Option<Move>, prior: f32) -> Self {
+        Self {
+            mv,
+            first_child: 0,
+            child_count: 0,
+            expanded: false,
+            terminal: None,
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+        }
+    }
+
+    fn children(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + self.child_count as usize
+    }
+
+    /// The mean result for the side that played `mv`.
+    fn mean(&self) -> f32 {
+        self.value_sum / self.visits as f32
+    }
+}
+
+/// A search tree kept between moves, so that the part of it
+/// still reachable after the moves played since can be reused.
+pub struct MctsTree {
+    root: Option<Board>,
+    nodes: Vec<MctsNode>,
+    max_nodes: usize,
+}
+
+impl MctsTree {
+    /// A tree using at most `tree_bytes`, counting the copy made while rerooting or pruning it.
+    /// Nothing is allocated until the first search.
+    pub fn new(tree_bytes: usize) -> Self {
+        let max_nodes = tree_bytes / 2 / std::mem::size_of::<MctsNode>();
+        Self {
+            root: None,
+            nodes: Vec::new(),
+            max_nodes: max_nodes.clamp(MIN_TREE_NODES, u32::MAX as usize),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.nodes.clear();
+    }
+
+    /// Makes `board` the root, keeping its subtree if it is the root or within two plies of it.
+    fn reuse(&mut self, board: &Board) {
+        let Some(root) = self.root.clone() else {
+            self.reset(board);
+            return;
+        };
+        if &root == board {
+            return;
+        }
+
+        let mut found = None;
+        'search: for child in self.nodes[0].children() {
+            let mut child_board = root.clone();
+            child_board.play_unchecked(self.nodes[child].mv.expect("missing move?"));
+            if &child_board == board {
+                found = Some(child);
+                break;
+            }
+            for grandchild in self.nodes[child].children() {
+                let mut grandchild_board = child_board.clone();
+                grandchild_board.play_unchecked(self.nodes[grandchild].mv.expect("missing move?"));
+                if &grandchild_board == board {
+                    found = Some(grandchild);
+                    break 'search;
+                }
+            }
+        }
+
+        match found {
+            Some(new_root) => self.reroot(new_root, board),
+            None => self.reset(board),
+        }
+    }
+
+    fn reset(&mut self, board: &Board) {
+        self.root = Some(board.clone());
+        self.nodes.clear();
+        self.nodes.reserve_exact(self.max_nodes);
+        self.nodes.push(MctsNode::new(None, 1.0));
+    }
+
+    /// Copies the subtree under `new_root` into a fresh arena, keeping children contiguous.
+    fn reroot(&mut self, new_root: usize, board: &Board) {
+        let mut nodes = Vec::with_capacity(self.max_nodes);
+        nodes.push(MctsNode { mv: None, ..self.nodes[new_root] });
+        let mut queue = vec![(new_root, 0)];
+        while let Some((old, new)) = queue.pop() {
+            let first_child = nodes.len();
+            for child in self.nodes[old].children() {
+                queue.push((child, nodes.len()));
+                nodes.push(self.nodes[child]);
+            }
+            nodes[new].first_child = first_child as u32;
+        }
+        self.root = Some(board.clone());
+        self.nodes = nodes;
+    }
+
+    /// Whether the tree might not have room for another expansion.
+    fn is_full(&self) -> bool {
+        self.nodes.len() + MAX_MOVES > self.max_nodes
+    }
+
+    /// Makes room by copying the most visited part of the tree into a fresh arena, filling at most half of it.
+    /// Nodes whose children don't fit become unexpanded leaves again, keeping their statistics.
+    fn prune(&mut self) {
+        let budget = self.max_nodes / 2;
+        let leaf = |node: MctsNode| MctsNode {
+            first_child: 0,
+            child_count: 0,
+            expanded: node.terminal.is_some(),
+            ..node
+        };
+        let mut nodes = Vec::with_capacity(self.max_nodes);
+        nodes.push(leaf(self.nodes[0]));
+        let mut queue = BinaryHeap::from([(self.nodes[0].visits, 0, 0)]);
+        while let Some((_, old, new)) = queue.pop() {
+            let children = self.nodes[old].children();
+            if children.is_empty() || nodes.len() + children.len() > budget {
+                continue;
+            }
+            nodes[new] = MctsNode {
+                first_child: nodes.len() as u32,
+                ..self.nodes[old]
+            };
+            for child in children {
+                queue.push((self.nodes[child].visits, child, nodes.len()));
+                nodes.push(leaf(self.nodes[child]));
+            }
+        }
+        self.nodes = nodes;
+    }
+}
+
+/// A PUCT search that plays out to one new position at a time, valued by the static eval.
+pub struct MctsSearch<'s> {
+    tree: &'s mut MctsTree,
+    time_manager: TimeManager,
+    max_depth: Option<u8>,
+    playouts: u64,
+    depth_sum: u64,
+}
+
+impl<'s> MctsSearch<'s> {
+    pub fn new(tree: &'s mut MctsTree, limits: SearchLimits, move_overhead: Duration) -> Self {
+        let max_depth = match limits {
+            SearchLimits::PerGame { .. } => None,
+            SearchLimits::PerMove { depth } => Some(depth),
+        };
+
+        Self {
+            tree,
+            time_manager: TimeManager::new(limits, move_overhead),
+            max_depth,
+            playouts: 0,
+            depth_sum: 0,
+        }
+    }
+
+    /// Reports a [`SearchInfo`] every time the number of playouts doubles, and once more at the end.
+    pub fn start(mut self, init_pos: &Board, moves_played: &[Move], on_iter: &mut dyn FnMut(SearchInfo)) {
+        let mut board = BoardStack::new(init_pos, moves_played);
+        self.tree.reuse(board.get());
+
+        let mut prev_ranks = Vec::new();
+        let mut reported = false;
+        loop {
+            if self.root_is_decided() {
+                break;
+            }
+            if self.tree.is_full() {
+                self.tree.prune();
+            }
+            let depth = self.playout(&mut board);
+            self.playouts += 1;
+            self.depth_sum += depth as u64;
+
+            // Reporting as the playouts double stands in for the iterations of the alpha-beta search.
+            reported = self.playouts.is_power_of_two() && self.tree.nodes[0].visits > 1;
+            if reported {
+                let info = self.info(self.depth().max(1), &mut prev_ranks);
+                // Visits from earlier searches count too, so the best move's share is of the whole tree.
+                let total_visits = self.tree.nodes[0].visits as u64;
+                self.time_manager.update(info.best_move, info.eval, info.root_moves[0].nodes, total_visits);
+                on_iter(info);
+            }
+
+            if self.max_depth.is_some_and(|max_depth| self.depth() >= max_depth) {
+                break;
+            }
+            if self.playouts.is_multiple_of(CHECK_INTERVAL) {
+                let out_of_playouts = self.max_depth.is_some() && self.playouts >= MAX_FIXED_DEPTH_PLAYOUTS;
+                if out_of_playouts || self.time_manager.soft_limit_reached() {
+                    break;
+                }
+            }
+        }
+
+        if self.tree.nodes[0].children().is_empty() || reported {
+            // Without any moves there is nothing to report, as with the alpha-beta search.
+            return;
+        }
+        let info = self.info(self.depth().max(1), &mut prev_ranks);
+        on_iter(info);
+    }
+
+    /// Selects a path down the tree, expands the position at its end and backs up its value.
+    /// Returns the length of the path.
+    fn playout(&mut self, board: &mut BoardStack) -> usize {
+        let mut path = vec![0];
+        let mut node = 0;
+        while self.tree.nodes[node].expanded && self.tree.nodes[node].terminal.is_none() {
+            node = self.select_child(node);
+            board.play_unchecked(self.tree.nodes[node].mv.expect("missing move?"));
+            path.push(node);
+        }
+
+        let value = match self.tree.nodes[node].terminal {
+            Some(value) => value,
+            None => self.expand(node, board, path.len() > 1),
+        };
+
+        // Each node's value is for the side that moved into it, the opponent of the side to move there.
+        let mut value = 1.0 - value;
+        for &node in path.iter().rev() {
+            let node = &mut self.tree.nodes[node];
+            node.visits += 1;
+            node.value_sum += value;
+            value = 1.0 - value;
+        }
+        for _ in 1..path.len() {
+            board.undo();
+        }
+        path.len() - 1
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let parent = &self.tree.nodes[node];
+        let parent_value = match parent.visits {
+            0 => 0.5,
+            _ => 1.0 - parent.mean(),
+        };
+        let fpu = (parent_value - FPU_REDUCTION).max(0.0);
+        let exploration = EXPLORATION * (parent.visits as f32).sqrt();
+
+        parent.children()
+            .max_by(|&a, &b| {
+                let puct = |index: usize| {
+                    let child = &self.tree.nodes[index];
+                    let q = match child.visits {
+                        0 => fpu,
+                        _ => child.mean(),
+                    };
+                    q + exploration * child.prior / (1.0 + child.visits as f32)
+                };
+                puct(a).total_cmp(&puct(b))
+            })
+            .expect("missing child?")
+    }
+
+    /// Adds the children of `node`, with priors from a softmax over their evals,
+    /// and returns the value of its position for the side to move.
+    fn expand(&mut self, node: usize, board: &BoardStack, check_draws: bool) -> f32 {
+        let position = board.get();
+        let terminal = match position.status() {
+            GameStatus::Won => Some(0.0),
+            GameStatus::Drawn => Some(0.5),
+            GameStatus::Ongoing if check_draws && board.is_draw() => Some(0.5),
+            GameStatus::Ongoing => None,
+        };
+        self.tree.nodes[node].expanded = true;
+        if let Some(value) = terminal {
+            self.tree.nodes[node].terminal = Some(value);
+            return value;
+        }
+
+        let mut children = Vec::new();
+        position.generate_moves(|moves| {
+            for mv in moves {
+                let mut child = position.clone();
+                child.play_unchecked(mv);
+                children.push((mv, -evaluate(&child).get() as f32 / POLICY_TEMPERATURE));
+            }
+            false
+        });
+        let max_logit = children.iter().map(|&(_, logit)| logit).fold(f32::MIN, f32::max);
+        let total: f32 = children.iter().map(|&(_, logit)| (logit - max_logit).exp()).sum();
+
+        let first_child = self.tree.nodes.len();
+        for &(mv, logit) in &children {
+            let prior = (logit - max_logit).exp() / total;
+            self.tree.nodes.push(MctsNode::new(Some(mv), prior));
+        }
+        let node = &mut self.tree.nodes[node];
+        node.first_child = first_child as u32;
+        node.child_count = children.len() as u8;
+
+        win_probability(evaluate(position))
+    }
+
+    /// Whether the game being over or a mate in one at the root makes further search pointless.
+    fn root_is_decided(&self) -> bool {
+        self.tree.nodes[0].terminal.is_some() || self.tree.nodes[0].children().any(|child| self.tree.nodes[child].terminal == Some(0.0))
+    }
+
+    /// The average depth of the playouts so far.
+    fn depth(&self) -> u8 {
+        (self.depth_sum as f64 / self.playouts.max(1) as f64).round().min(u8::MAX as f64) as u8
+    }
+
+    fn best_child(&self, node: usize) -> Option<usize> {
+        self.tree.nodes[node].children().max_by_key(|&child| {
+            let child = &self.tree.nodes[child];
+            // A mate is best no matter how few playouts it got.
+            (child.terminal == Some(0.0), child.visits)
+        })
+    }
+
+    fn info(&self, depth: u8, prev_ranks: &mut Vec<Move>) -> SearchInfo {
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while let Some(child) = self.best_child(node).filter(|&child| self.tree.nodes[child].visits > 0) {
+            pv.push(self.tree.nodes[child].mv.expect("missing move?"));
+            node = child;
+        }
+        let best = self.best_child(0).expect("missing best move?");
+        let best_move = self.tree.nodes[best].mv.expect("missing move?");
+        let pv = match pv.is_empty() {
+            true => vec![best_move],
+            false => pv,
+        };
+
+        let mut root_moves = self.tree.nodes[0].children()
+            .map(|child| {
+                let child = &self.tree.nodes[child];
+                let mv = child.mv.expect("missing move?");
+                RootMove {
+                    mv,
+                    score: self.child_score(child),
+                    exact: child.visits > 0,
+                    nodes: child.visits as u64,
+                    prev_rank: prev_ranks.iter().position(|&prev| prev == mv).unwrap_or(prev_ranks.len()),
+                }
+            })
+            .collect::<Vec<_>>();
+        root_moves.sort_by_key(|root_move| (root_move.mv != best_move, std::cmp::Reverse(root_move.nodes)));
+        *prev_ranks = root_moves.iter().map(|root_move| root_move.mv).collect();
+
+        SearchInfo {
+            depth,
+            nodes: self.playouts,
+            eval: self.child_score(&self.tree.nodes[best]),
+            time: self.time_manager.elapsed(),
+            best_move,
+            pv,
+            root_moves,
+            // None of the alpha-beta counters apply.
+            stats: SearchStats::default(),
+        }
+    }
+
+    /// The score of a root move for the side to move at the root.
+    fn child_score(&self, child: &MctsNode) -> Score {
+        match child.terminal {
+            Some(0.0) => Score::mate_in(1),
+            _ if child.visits == 0 => -Score::INFINITY,
+            _ => eval_from_probability(child.mean()),
+        }
+    }
+}
+
+fn win_probability(eval: Score) -> f32 {
+    1.0 / (1.0 + (-eval.get() as f32 / EVAL_SCALE).exp())
+}
+
+fn eval_from_probability(probability: f32) -> Score {
+    let probability = probability.clamp(1e-6, 1.0 - 1e-6);
+    Score::new((EVAL_SCALE * (probability / (1.0 - probability)).ln()) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TREE_BYTES: usize = 16 * 1024 * 1024;
+
+    fn search(tree: &mut MctsTree, board: &Board, moves: &[Move], depth: u8) -> SearchInfo {
+        let mut result = None;
+        let search = MctsSearch::new(tree, SearchLimits::PerMove { depth }, Duration::ZERO);
+        search.start(board, moves, &mut |info| result = Some(info));
+        result.expect("missing search result?")
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let board = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1".parse::<Board>().unwrap();
+        let info = search(&mut MctsTree::new(TEST_TREE_BYTES), &board, &[], 4);
+        assert_eq!(info.best_move, "a1a8".parse::<Move>().unwrap());
+        assert_eq!(info.eval, Score::mate_in(1));
+    }
+
+    #[test]
+    fn wins_material() {
+        // The queen on d5 is hanging.
+        let board = "4k3/8/8/3q4/8/2N5/8/4K3 w - - 0 1".parse::<Board>().unwrap();
+        let info = search(&mut MctsTree::new(TEST_TREE_BYTES), &board, &[], 3);
+        assert_eq!(info.best_move, "c3d5".parse::<Move>().unwrap());
+    }
+
+    #[test]
+    fn reuses_tree_after_moves() {
+        let board = Board::startpos();
+        let mut tree = MctsTree::new(TEST_TREE_BYTES);
+        search(&mut tree, &board, &[], 4);
+
+        let most_visited = |tree: &MctsTree, node: usize| {
+            tree.nodes[node].children().max_by_key(|&child| tree.nodes[child].visits).expect("missing child?")
+        };
+        let child = most_visited(&tree, 0);
+        let grandchild = most_visited(&tree, child);
+        let kept = tree.nodes[grandchild].visits;
+        assert!(kept > 1);
+
+        let mut after = board.clone();
+        after.play(tree.nodes[child].mv.unwrap());
+        after.play(tree.nodes[grandchild].mv.unwrap());
+        tree.reuse(&after);
+        assert_eq!(tree.nodes[0].visits, kept);
+        assert_eq!(tree.root.as_ref(), Some(&after));
+
+        tree.reuse(&Board::startpos());
+        assert_eq!(tree.nodes.len(), 1);
+    }
+
+    #[test]
+    fn prunes_when_full() {
+        let board = Board::startpos();
+        let mut tree = MctsTree::new(TEST_TREE_BYTES);
+        search(&mut tree, &board, &[], 4);
+        let visits = tree.nodes[0].visits;
+        let best = tree.nodes[0].children().max_by_key(|&child| tree.nodes[child].visits).unwrap();
+        let best = (tree.nodes[best].mv, tree.nodes[best].visits);
+
+        tree.max_nodes = MIN_TREE_NODES;
+        assert!(tree.nodes.len() > tree.max_nodes / 2);
+        tree.prune();
+        assert!(tree.nodes.len() <= tree.max_nodes / 2);
+        assert_eq!(tree.nodes[0].visits, visits);
+        // The root is always kept expanded, along with the statistics of its children.
+        let kept = tree.nodes[0].children().find(|&child| tree.nodes[child].mv == best.0).unwrap();
+        assert_eq!(tree.nodes[kept].visits, best.1);
+        for node in &tree.nodes {
+            assert!(node.children().end <= tree.nodes.len());
+            assert!(node.expanded || node.children().is_empty());
+        }
+    }
+}
@@ -1,11 +1,30 @@
 mod board_stack;
+mod cuckoo;
 mod movelist;
 mod eval;
+mod score;
 mod tt;
 mod history_tables;
 mod helpers;
 mod search;
+mod search_stack;
+mod root_moves;
+mod time_manager;
+mod stats;
+mod params;
+mod tree;
+mod mate;
+mod explain;
+mod mcts;
 mod interface;
 
-pub use interface::Engine;
+pub use interface::{Engine, SearchAlgorithm};
 pub use search::SearchLimits;
+pub use score::Score;
+pub use stats::SearchStats;
+pub use tree::TreeDumpConfig;
+pub use mate::MateResult;
+pub use explain::MoveExplanation;
+pub use tt::TtBound;
+#[cfg(feature = "tune")]
+pub use params::SearchParams;
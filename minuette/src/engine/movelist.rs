@@ -1,43 +1,235 @@
 use arrayvec::ArrayVec;
-use cozy_chess::{Board, Move};
+use cozy_chess::{Board, Move, Piece, BitBoard, Rank, Square};
 
-use super::tt::TtEntry;
 use super::history_tables::HistoryTables;
-use super::helpers::{move_is_capture, captured_piece};
+use super::helpers::{captured_piece, gives_check, see};
 
-type MoveList = ArrayVec<Move, 218>;
+type ScoredMoveList = ArrayVec<(Move, i32), 218>;
+
+/// Puts quiet moves that escape a threatened piece ahead of every other quiet.
+const THREAT_ESCAPE_BONUS: i32 = 1 << 20;
+/// Puts quiet checks ahead of every other quiet, short of threat escapes.
+const CHECK_BONUS: i32 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerMode {
+    All,
+    /// Only captures and queen promotions that don't lose material.
+    Captures,
+    /// As [`PickerMode::Captures`], followed by quiet checks that don't lose material.
+    CapturesAndChecks,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum MoveScore {
-    Quiet(i32),
-    Capture(i32),
-    PvMove,
+enum Stage {
+    TtMove,
+    GenCaptures,
+    GoodCaptures,
+    Killers,
+    GenQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
 }
 
-pub fn get_ordered_moves(board: &Board, tt_entry: Option<TtEntry>, history: &HistoryTables, qsearch: bool) -> MoveList {
-    let mut movelist = MoveList::new();
-    board.generate_moves(|packed_moves| {
-        movelist.extend(packed_moves);
-        false
-    });
+/// Lazily yields moves in stages, so that a cutoff on an early move
+/// skips generating and scoring everything after it.
+pub struct MovePicker {
+    stage: Stage,
+    mode: PickerMode,
+    skip_quiets: bool,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    killer_index: usize,
+    threatened: Option<Square>,
+    moves: ScoredMoveList,
+    index: usize,
+    bad_captures: ArrayVec<Move, 218>,
+    bad_index: usize,
+}
 
-    if qsearch {
-        movelist.retain(|&mut mv| move_is_capture(board, mv));
+impl MovePicker {
+    /// Queen promotions are picked along with captures.
+    /// Quiet moves of the piece on `threatened` are tried first, then quiet checks.
+    pub fn new(tt_move: Option<Move>, killers: [Option<Move>; 2], threatened: Option<Square>, mode: PickerMode) -> Self {
+        Self {
+            stage: Stage::TtMove,
+            mode,
+            skip_quiets: false,
+            tt_move,
+            killers,
+            killer_index: 0,
+            threatened,
+            moves: ScoredMoveList::new(),
+            index: 0,
+            bad_captures: ArrayVec::new(),
+            bad_index: 0,
+        }
     }
 
-    let key_fn = |mv| {
-        if Some(mv) == tt_entry.and_then(|entry| entry.best_move) {
-            return MoveScore::PvMove;
-        }
+    /// Stop yielding quiet moves other than checks.
+    /// Captures that are still pending are yielded as usual.
+    pub fn skip_quiets(&mut self) {
+        self.skip_quiets = true;
+    }
 
-        if let Some(victim) = captured_piece(board, mv) {
-            let attacker = board.piece_on(mv.from).expect("missing attacker?");
-            return MoveScore::Capture(victim as i32 * 8 - attacker as i32);
+    pub fn next(&mut self, board: &Board, history: &HistoryTables) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenCaptures;
+                    let Some(mv) = self.tt_move else {
+                        continue;
+                    };
+                    if !board.is_legal(mv) {
+                        self.tt_move = None;
+                        continue;
+                    }
+                    let allowed = match self.mode {
+                        PickerMode::All => true,
+                        PickerMode::Captures => is_noisy(board, mv) && see(board, mv, 0),
+                        PickerMode::CapturesAndChecks => {
+                            (is_noisy(board, mv) || gives_check(board, mv)) && see(board, mv, 0)
+                        }
+                    };
+                    if !allowed {
+                        continue;
+                    }
+                    return Some(mv);
+                }
+                Stage::GenCaptures => {
+                    let enemies = board.colors(!board.side_to_move());
+                    let promotions = Rank::Eighth.relative_to(board.side_to_move()).bitboard();
+                    let pawn_targets = enemies | ep_target(board) | promotions;
+                    self.generate(board, pawn_targets, enemies, is_noisy, |board, mv| {
+                        let victim = captured_piece(board, mv).map_or(0, |victim| victim as i32 * 8);
+                        let promotion = mv.promotion.map_or(0, |promotion| promotion as i32 * 8);
+                        let attacker = board.piece_on(mv.from).expect("missing attacker?");
+                        victim + promotion - attacker as i32
+                    });
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    let Some(mv) = self.pick_best() else {
+                        self.stage = match self.mode {
+                            PickerMode::All => Stage::Killers,
+                            PickerMode::Captures => Stage::Done,
+                            PickerMode::CapturesAndChecks => Stage::GenQuiets,
+                        };
+                        continue;
+                    };
+                    if !see(board, mv, 0) {
+                        if self.mode == PickerMode::All {
+                            self.bad_captures.push(mv);
+                        }
+                        continue;
+                    }
+                    return Some(mv);
+                }
+                Stage::Killers => {
+                    if self.killer_index >= self.killers.len() {
+                        self.stage = Stage::GenQuiets;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+                    let Some(mv) = killer else {
+                        continue;
+                    };
+                    let is_usable = killer != self.tt_move && !is_noisy(board, mv) && board.is_legal(mv);
+                    if is_usable && (!self.skip_quiets || gives_check(board, mv)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::GenQuiets => {
+                    let enemies = board.colors(!board.side_to_move());
+                    let pawn_targets = !(enemies | ep_target(board));
+                    let threatened = self.threatened;
+                    let checks_only = self.skip_quiets || self.mode == PickerMode::CapturesAndChecks;
+                    let filter = |board: &Board, mv| {
+                        !is_noisy(board, mv) && (!checks_only || gives_check(board, mv))
+                    };
+                    self.generate(board, pawn_targets, !enemies, filter, |board, mv| {
+                        let escape = Some(mv.from) == threatened;
+                        let check = gives_check(board, mv);
+                        history.get_quiet_score(board, mv)
+                            + escape as i32 * THREAT_ESCAPE_BONUS
+                            + check as i32 * CHECK_BONUS
+                    });
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    let Some(mv) = self.pick_best() else {
+                        self.stage = Stage::BadCaptures;
+                        continue;
+                    };
+                    if self.killers.contains(&Some(mv)) {
+                        continue;
+                    }
+                    if self.skip_quiets && !gives_check(board, mv) {
+                        continue;
+                    }
+                    if self.mode == PickerMode::CapturesAndChecks && !see(board, mv, 0) {
+                        continue;
+                    }
+                    return Some(mv);
+                }
+                Stage::BadCaptures => {
+                    let Some(&mv) = self.bad_captures.get(self.bad_index) else {
+                        self.stage = Stage::Done;
+                        continue;
+                    };
+                    self.bad_index += 1;
+                    return Some(mv);
+                }
+                Stage::Done => return None,
+            }
         }
+    }
 
-        MoveScore::Quiet(history.get_quiet_score(board, mv))
-    };
-    movelist.sort_by_key(|&mv| std::cmp::Reverse(key_fn(mv)));
+    fn generate(
+        &mut self,
+        board: &Board,
+        pawn_targets: BitBoard,
+        piece_targets: BitBoard,
+        filter: impl Fn(&Board, Move) -> bool,
+        mut score: impl FnMut(&Board, Move) -> i32,
+    ) {
+        self.moves.clear();
+        self.index = 0;
+        board.generate_moves(|mut moves| {
+            moves.to &= match moves.piece {
+                Piece::Pawn => pawn_targets,
+                _ => piece_targets,
+            };
+            for mv in moves {
+                if Some(mv) != self.tt_move && filter(board, mv) {
+                    self.moves.push((mv, score(board, mv)));
+                }
+            }
+            false
+        });
+    }
+
+    fn pick_best(&mut self) -> Option<Move> {
+        let remaining = self.moves.get(self.index..)?;
+        let (best, _) = remaining
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, &(_, score))| score)?;
+        self.moves.swap(self.index, self.index + best);
+        self.index += 1;
+        Some(self.moves[self.index - 1].0)
+    }
+}
+
+fn ep_target(board: &Board) -> BitBoard {
+    match board.en_passant() {
+        Some(file) => Square::new(file, Rank::Sixth.relative_to(board.side_to_move())).bitboard(),
+        None => BitBoard::EMPTY,
+    }
+}
 
-    movelist
+fn is_noisy(board: &Board, mv: Move) -> bool {
+    mv.promotion == Some(Piece::Queen) || captured_piece(board, mv).is_some()
 }
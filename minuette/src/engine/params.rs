@@ -0,0 +1,111 @@
+#[cfg(feature = "tune")]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+macro_rules! search_params {
+    ($($name:ident: $default:expr, $min:expr, $max:expr, $step:expr;)*) => {
+        /// Search heuristic constants, exposed for tuning.
+        #[derive(Debug, Clone)]
+        pub struct SearchParams {
+            $(pub $name: i32,)*
+        }
+
+        impl Default for SearchParams {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        #[cfg(feature = "tune")]
+        impl SearchParams {
+            pub const SPECS: &'static [ParamSpec] = &[
+                $(ParamSpec {
+                    name: stringify!($name),
+                    default: $default,
+                    min: $min,
+                    max: $max,
+                    step: $step,
+                },)*
+            ];
+
+            /// Sets a parameter by name, returning whether it exists.
+            pub fn set(&mut self, name: &str, value: i32) -> bool {
+                match name {
+                    $(stringify!($name) => self.$name = value,)*
+                    _ => return false,
+                }
+                true
+            }
+        }
+    };
+}
+
+search_params! {
+    rfp_max_depth: 4, 1, 10, 1;
+    rfp_margin: 80, 20, 200, 10;
+    razor_max_depth: 2, 1, 5, 1;
+    razor_margin: 250, 50, 600, 25;
+    nmp_min_depth: 2, 1, 6, 1;
+    nmp_base_reduction: 2, 1, 5, 1;
+    nmp_eval_divisor: 200, 50, 500, 20;
+    nmp_verify_min_depth: 12, 6, 20, 1;
+    probcut_min_depth: 5, 3, 10, 1;
+    probcut_margin: 200, 50, 500, 20;
+    probcut_reduction: 4, 2, 6, 1;
+    lmp_max_depth: 4, 1, 10, 1;
+    lmp_base: 7, 1, 20, 1;
+    lmp_scale: 3, 1, 10, 1;
+    lmp_improving_scale: 2, 0, 10, 1;
+    fp_max_depth: 6, 1, 12, 1;
+    fp_base: 100, 0, 300, 15;
+    fp_scale: 80, 20, 200, 10;
+    see_max_depth: 6, 1, 12, 1;
+    see_capture_margin: 100, 20, 200, 10;
+    see_quiet_margin: 60, 10, 150, 5;
+    se_min_depth: 8, 4, 12, 1;
+    se_tt_depth_margin: 3, 1, 6, 1;
+    se_beta_scale: 2, 1, 8, 1;
+    iir_min_depth: 4, 2, 10, 1;
+    ext_ply_scale: 2, 1, 4, 1;
+    lmr_base: 77, 0, 200, 10;
+    lmr_divisor: 226, 100, 400, 15;
+    lmr_pv: 1024, 0, 3072, 128;
+    lmr_cut_node: 1024, 0, 3072, 128;
+    lmr_improving: 1024, 0, 3072, 128;
+    lmr_check: 1024, 0, 3072, 128;
+    lmr_tt_capture: 1024, 0, 3072, 128;
+    lmr_threat_escape: 1024, 0, 3072, 128;
+    lmr_history_divisor: 200, 50, 1000, 25;
+    delta_margin: 200, 0, 500, 20;
+    max_history: 512, 128, 4096, 64;
+}
+
+#[cfg(feature = "tune")]
+impl SearchParams {
+    pub fn print_uci_options() {
+        for spec in Self::SPECS {
+            println!(
+                "option name {} type spin default {} min {} max {}",
+                spec.name, spec.default, spec.min, spec.max,
+            );
+        }
+    }
+
+    /// Prints the parameters in the SPSA input format OpenBench expects:
+    /// `name, int, default, min, max, c_end, r_end`.
+    pub fn print_spsa_inputs() {
+        for spec in Self::SPECS {
+            println!(
+                "{}, int, {}, {}, {}, {}, 0.002",
+                spec.name, spec.default, spec.min, spec.max, spec.step,
+            );
+        }
+    }
+}
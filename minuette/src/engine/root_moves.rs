@@ -0,0 +1,81 @@
+use std::cmp::Reverse;
+
+use cozy_chess::{Board, Move};
+
+use super::history_tables::HistoryTables;
+use super::movelist::{MovePicker, PickerMode};
+use super::score::Score;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RootMove {
+    pub mv: Move,
+    /// The score from the last iteration that searched this move.
+    /// Only moves that raised alpha have exact scores; the rest are upper bounds.
+    pub score: Score,
+    pub exact: bool,
+    /// Nodes spent searching this move, over all iterations.
+    pub nodes: u64,
+    /// The rank of this move after the previous iteration, which is the order it was searched in.
+    pub prev_rank: usize,
+}
+
+/// Every legal move at the root, searched in order of the previous iteration's scores.
+#[derive(Default)]
+pub struct RootMoves {
+    moves: Vec<RootMove>,
+}
+
+impl RootMoves {
+    /// Before the first iteration, moves are in the usual move ordering.
+    pub fn new(board: &Board, tt_move: Option<Move>, history: &HistoryTables) -> Self {
+        let mut moves = Vec::new();
+        let mut picker = MovePicker::new(tt_move, [None; 2], None, PickerMode::All);
+        while let Some(mv) = picker.next(board, history) {
+            moves.push(RootMove {
+                mv,
+                score: -Score::INFINITY,
+                exact: false,
+                nodes: 0,
+                prev_rank: moves.len(),
+            });
+        }
+        Self { moves }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Move> {
+        self.moves.get(index).map(|root_move| root_move.mv)
+    }
+
+    pub fn nodes(&self, mv: Move) -> u64 {
+        self.find(mv).nodes
+    }
+
+    pub fn update(&mut self, mv: Move, score: Score, exact: bool, nodes: u64) {
+        let root_move = self.find_mut(mv);
+        root_move.score = score;
+        root_move.exact = exact;
+        root_move.nodes += nodes;
+    }
+
+    /// Orders the moves for the next iteration after a completed one. Moves that raised alpha
+    /// come first, best first. The rest keep their relative order, since an upper bound
+    /// from a null window search says little about how they compare.
+    pub fn end_iteration(&mut self) {
+        for (rank, root_move) in self.moves.iter_mut().enumerate() {
+            root_move.prev_rank = rank;
+        }
+        self.moves.sort_by_key(|root_move| Reverse(root_move.exact.then_some(root_move.score)));
+    }
+
+    pub fn moves(&self) -> &[RootMove] {
+        &self.moves
+    }
+
+    fn find(&self, mv: Move) -> &RootMove {
+        self.moves.iter().find(|root_move| root_move.mv == mv).expect("missing root move?")
+    }
+
+    fn find_mut(&mut self, mv: Move) -> &mut RootMove {
+        self.moves.iter_mut().find(|root_move| root_move.mv == mv).expect("missing root move?")
+    }
+}
@@ -0,0 +1,141 @@
+use std::ops::{Add, Neg, Sub};
+
+const MATE: i16 = 30_000;
+const MAX_MATE_PLY: i16 = 256;
+
+/// A search score from the point of view of the side to move.
+/// Evals are kept below the mate range. Arithmetic saturates at [`Score::INFINITY`],
+/// so margins can't overflow, but a large enough margin can still carry an eval past
+/// [`Score::MAX_EVAL`]; check the result with [`Score::is_mate`] where that matters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Score(i16);
+
+impl Score {
+    pub const DRAW: Score = Score(0);
+    pub const INFINITY: Score = Score(31_000);
+    pub const MAX_EVAL: Score = Score(MATE - MAX_MATE_PLY - 1);
+
+    /// A static eval or other heuristic score, clamped below the mate range.
+    pub fn new(eval: i32) -> Self {
+        Self(eval.clamp(-Self::MAX_EVAL.0 as i32, Self::MAX_EVAL.0 as i32) as i16)
+    }
+
+    /// The side to move mates in `ply` plies.
+    pub const fn mate_in(ply: u16) -> Self {
+        Self(MATE - ply as i16)
+    }
+
+    /// The side to move is mated in `ply` plies.
+    pub const fn mated_in(ply: u16) -> Self {
+        Self(-Self::mate_in(ply).0)
+    }
+
+    pub const fn get(self) -> i16 {
+        self.0
+    }
+
+    /// Whether this is a mate score (or infinite) rather than an eval.
+    pub const fn is_mate(self) -> bool {
+        self.0.abs() > Self::MAX_EVAL.0
+    }
+
+    /// The plies until mate, positive if the side to move is mating.
+    pub fn mate_distance(self) -> Option<i16> {
+        match () {
+            _ if !self.is_mate() || self.0.abs() > MATE => None,
+            _ if self.0 > 0 => Some(MATE - self.0),
+            _ => Some(-MATE - self.0),
+        }
+    }
+
+    /// Converts a mate score relative to the root into one relative to the node at `ply`,
+    /// so that it stays correct when the entry is found at a different ply.
+    pub fn to_tt(self, ply: u16) -> Self {
+        match () {
+            _ if !self.is_mate() => self,
+            _ if self.0 > 0 => Self(self.0 + ply as i16),
+            _ => Self(self.0 - ply as i16),
+        }
+    }
+
+    /// The inverse of [`Score::to_tt`].
+    pub fn to_search(self, ply: u16) -> Self {
+        match () {
+            _ if !self.is_mate() => self,
+            _ if self.0 > 0 => Self(self.0 - ply as i16),
+            _ => Self(self.0 + ply as i16),
+        }
+    }
+
+    fn saturate(score: i32) -> Self {
+        Self(score.clamp(-Self::INFINITY.0 as i32, Self::INFINITY.0 as i32) as i16)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl Add<i16> for Score {
+    type Output = Score;
+
+    fn add(self, rhs: i16) -> Score {
+        Score::saturate(self.0 as i32 + rhs as i32)
+    }
+}
+
+impl Sub<i16> for Score {
+    type Output = Score;
+
+    fn sub(self, rhs: i16) -> Score {
+        Score::saturate(self.0 as i32 - rhs as i32)
+    }
+}
+
+/// The difference between two scores, such as an eval error or a score drop.
+impl Sub for Score {
+    type Output = i32;
+
+    fn sub(self, rhs: Score) -> i32 {
+        self.0 as i32 - rhs.0 as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mate_scores() {
+        assert!(Score::mate_in(3).is_mate());
+        assert!(Score::mated_in(4).is_mate());
+        assert!(!Score::MAX_EVAL.is_mate());
+        assert_eq!(Score::mate_in(3).mate_distance(), Some(3));
+        assert_eq!(Score::mated_in(4).mate_distance(), Some(-4));
+        assert_eq!(Score::new(150).mate_distance(), None);
+        assert_eq!(Score::INFINITY.mate_distance(), None);
+        assert_eq!(-Score::mate_in(5), Score::mated_in(5));
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        assert_eq!(Score::new(i32::MAX), Score::MAX_EVAL);
+        assert_eq!(Score::INFINITY + 1000, Score::INFINITY);
+        assert_eq!(-Score::INFINITY - i16::MAX, -Score::INFINITY);
+        assert_eq!(Score::new(100) + 50, Score::new(150));
+    }
+
+    #[test]
+    fn tt_ply_adjustment() {
+        // Mate in 7 from the root, found at ply 3, is mate in 4 from that node.
+        let score = Score::mate_in(7);
+        assert_eq!(score.to_tt(3), Score::mate_in(4));
+        assert_eq!(score.to_tt(3).to_search(5), Score::mate_in(9));
+        assert_eq!(Score::mated_in(6).to_tt(2), Score::mated_in(4));
+        assert_eq!(Score::new(42).to_tt(10), Score::new(42));
+    }
+}
@@ -1,278 +1,725 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use cozy_chess::{Board, Piece, Move, GameStatus};
+use arrayvec::ArrayVec;
+use cozy_chess::{Board, Color, Piece, Move, GameStatus, Rank};
 
 use super::board_stack::BoardStack;
-use super::movelist::get_ordered_moves;
-use super::eval::{evaluate, CHECKMATE, INFINITY};
+use super::movelist::{MovePicker, PickerMode};
+use super::eval::evaluate;
+use super::score::Score;
 use super::tt::{TranspositionTable, TtEntry, TtBound};
 use super::history_tables::HistoryTables;
-use super::helpers::move_is_capture;
+use super::params::SearchParams;
+use super::time_manager::TimeManager;
+use super::stats::{SearchStats, stat};
+use super::tree::{SearchTree, Edge, Outcome};
+use super::helpers::{move_is_capture, captured_piece, gives_check, see, SEE_VALUES};
+use super::search_stack::{SearchStack, MAX_PLY};
+use super::root_moves::{RootMoves, RootMove};
+
+/// Reductions are computed in fractions of a ply, so that adjustments can be finer than a ply.
+const LMR_GRAIN: i32 = 1024;
+const LMR_TABLE_SIZE: usize = 64;
 
 #[derive(Debug, Clone, Copy)]
 pub enum SearchLimits {
     PerGame {
         clock: Duration,
         increment: Duration,
+        moves_to_go: Option<u32>,
     },
     PerMove {
         depth: u8,
     },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct SearchInfo {
     pub depth: u8,
     pub nodes: u64,
-    pub eval: i16,
+    pub eval: Score,
     pub time: Duration,
     pub best_move: Move,
+    pub pv: Vec<Move>,
+    /// Every root move, in the order the next iteration would search them.
+    pub root_moves: Vec<RootMove>,
+    pub stats: SearchStats,
 }
 
 pub struct Search<'s> {
     tt: &'s mut TranspositionTable,
     history: &'s mut HistoryTables,
-    search_start: Instant,
-    soft_limit: Duration,
-    hard_limit: Duration,
+    params: &'s SearchParams,
+    time_manager: TimeManager,
     max_depth: u8,
+    root_depth: i32,
     best_move: Option<Move>,
     nodes: u64,
+    root_moves: RootMoves,
+    stack: SearchStack,
+    lmr_table: [[i32; LMR_TABLE_SIZE]; LMR_TABLE_SIZE],
+    /// Null move pruning is disabled for `nmp_color` before this ply, during a verification search.
+    nmp_min_ply: u16,
+    nmp_color: Color,
+    stats: SearchStats,
+    tree: Option<&'s mut SearchTree>,
 }
 
-impl<'s> Search<'s> {
-    pub fn new(tt: &'s mut TranspositionTable, history: &'s mut HistoryTables, limits: SearchLimits) -> Self {
-        let mut soft_limit = Duration::MAX;
-        let mut hard_limit = Duration::MAX;
-        let mut max_depth = u8::MAX;
-        match limits {
-            SearchLimits::PerGame { clock, increment } => {
-                soft_limit = clock / 40;
-                hard_limit = clock / 4;
-                let _ = increment; // pretend we account for increment
-            }
-            SearchLimits::PerMove { depth } => {
-                max_depth = depth;
-            }
+/// Base reductions in [`LMR_GRAIN`]ths of a ply, indexed by depth and move number.
+fn lmr_table(params: &SearchParams) -> [[i32; LMR_TABLE_SIZE]; LMR_TABLE_SIZE] {
+    let mut table = [[0; LMR_TABLE_SIZE]; LMR_TABLE_SIZE];
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+            let scale = (depth as f64).ln() * (move_number as f64).ln() * 100.0 / params.lmr_divisor as f64;
+            *reduction = ((params.lmr_base as f64 / 100.0 + scale) * LMR_GRAIN as f64) as i32;
         }
+    }
+    table
+}
+
+impl<'s> Search<'s> {
+    pub fn new(
+        tt: &'s mut TranspositionTable,
+        history: &'s mut HistoryTables,
+        params: &'s SearchParams,
+        limits: SearchLimits,
+        move_overhead: Duration,
+        tree: Option<&'s mut SearchTree>,
+    ) -> Self {
+        let max_depth = match limits {
+            SearchLimits::PerGame { .. } => u8::MAX,
+            SearchLimits::PerMove { depth } => depth,
+        };
 
         Self {
             tt,
             history,
-            search_start: Instant::now(),
-            soft_limit,
-            hard_limit,
+            params,
+            time_manager: TimeManager::new(limits, move_overhead),
             max_depth,
+            root_depth: 0,
             best_move: None,
             nodes: 0,
+            root_moves: RootMoves::default(),
+            stack: SearchStack::new(),
+            lmr_table: lmr_table(params),
+            nmp_min_ply: 0,
+            nmp_color: Color::White,
+            stats: SearchStats::default(),
+            tree,
         }
     }
 
     pub fn start(mut self, init_pos: &Board, moves_played: &[Move], on_iter: &mut dyn FnMut(SearchInfo)) {
         let mut board = BoardStack::new(init_pos, moves_played);
+        let tt_move = self.tt.load(board.get().hash(), 0).and_then(|entry| entry.best_move);
+        self.root_moves = RootMoves::new(board.get(), tt_move, self.history);
         for target_depth in 1..=self.max_depth {
-            let Some(eval) = self.negamax(&mut board, -INFINITY, INFINITY, target_depth as i32, 0) else {
+            self.trace(|tree| tree.begin_iteration());
+            self.root_depth = target_depth as i32;
+            let Some(eval) = self.negamax(&mut board, -Score::INFINITY, Score::INFINITY, target_depth as i32, 0, false) else {
                 break;
             };
+            self.trace(|tree| tree.end_iteration());
+            self.root_moves.end_iteration();
 
+            let best_move = self.best_move.expect("missing best move?");
             on_iter(SearchInfo {
                 depth: target_depth,
                 nodes: self.nodes,
                 eval,
-                time: self.search_start.elapsed(),
-                best_move: self.best_move.expect("missing best move?"),
+                time: self.time_manager.elapsed(),
+                best_move,
+                pv: self.stack[0].pv.to_vec(),
+                root_moves: self.root_moves.moves().to_vec(),
+                stats: SearchStats {
+                    nodes: self.nodes,
+                    ..self.stats
+                },
             });
 
-            if self.search_start.elapsed() >= self.soft_limit {
+            let best_move_nodes = self.root_moves.nodes(best_move);
+            self.time_manager.update(best_move, eval, best_move_nodes, self.nodes);
+            if self.time_manager.soft_limit_reached() {
                 break;
             }
         }
     }
 
-    fn negamax(&mut self, board: &mut BoardStack, mut alpha: i16, beta: i16, mut depth: i32, ply: u16) -> Option<i16> {
-        assert!((-INFINITY..=INFINITY).contains(&alpha));
-        assert!((-INFINITY..=INFINITY).contains(&beta));
+    /// Extensions are only allowed while the path is within a multiple of the root depth.
+    /// This bounds the total extension along any path, so that perpetual checks and
+    /// other chains of extensions can't grow the tree without limit.
+    fn can_extend(&self, ply: u16) -> bool {
+        (ply as i32) < self.root_depth * self.params.ext_ply_scale
+    }
+
+    /// Runs `f` on the search tree recorder, if the tree is being dumped.
+    fn trace(&mut self, f: impl FnOnce(&mut SearchTree)) {
+        if let Some(tree) = self.tree.as_deref_mut() {
+            f(tree);
+        }
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut BoardStack,
+        alpha: Score,
+        beta: Score,
+        depth: i32,
+        ply: u16,
+        cut_node: bool,
+    ) -> Option<Score> {
+        self.trace(|tree| tree.enter(ply, depth, alpha, beta, false));
+        let score = self.negamax_node(board, alpha, beta, depth, ply, cut_node);
+        self.trace(|tree| tree.exit(score));
+        score
+    }
+
+    fn negamax_node(
+        &mut self,
+        board: &mut BoardStack,
+        mut alpha: Score,
+        beta: Score,
+        mut depth: i32,
+        ply: u16,
+        cut_node: bool,
+    ) -> Option<Score> {
+        assert!((-Score::INFINITY..=Score::INFINITY).contains(&alpha));
+        assert!((-Score::INFINITY..=Score::INFINITY).contains(&beta));
         assert!(alpha < beta);
+        let params = self.params;
+        let excluded = self.stack[ply].excluded;
+        self.stack[ply].pv.clear();
 
-        if !board.get().checkers().is_empty() {
+        if !board.get().checkers().is_empty() && self.can_extend(ply) {
             depth = depth.max(0) + 1;
         }
 
+        if ply != 0 && board.is_draw() {
+            self.trace(|tree| tree.set_outcome(Outcome::Draw));
+            return Some(Score::DRAW);
+        }
+
         if depth <= 0 {
-            if board.repetitions() >= 3 {
-                return Some(0);
-            }
-            return Some(self.qsearch(board, alpha, beta, ply));
+            self.trace(|tree| tree.set_qsearch());
+            return Some(self.qsearch_node(board, alpha, beta, ply, true));
         }
 
         self.nodes += 1;
 
-        if self.nodes % 1024 == 0 && self.best_move.is_some() && self.search_start.elapsed() >= self.hard_limit {
+        if self.nodes.is_multiple_of(1024) && self.best_move.is_some() && self.time_manager.hard_limit_reached() {
             return None;
         }
 
         match board.get().status() {
-            GameStatus::Won => return Some(-CHECKMATE + ply as i16),
-            GameStatus::Drawn => return Some(0),
+            GameStatus::Won => {
+                self.trace(|tree| tree.set_outcome(Outcome::Terminal));
+                return Some(Score::mated_in(ply));
+            }
+            GameStatus::Drawn => {
+                self.trace(|tree| tree.set_outcome(Outcome::Terminal));
+                return Some(Score::DRAW);
+            }
             GameStatus::Ongoing => {},
         }
-        if board.repetitions() >= 3 {
-            return Some(0);
+        if ply >= MAX_PLY {
+            return Some(evaluate(board.get()));
         }
 
         let is_pv = alpha + 1 != beta;
+
+        // If we can repeat an earlier position with the next move, we're at least drawing.
+        if ply != 0 && alpha < Score::DRAW && board.has_upcoming_repetition() {
+            alpha = Score::DRAW;
+            if alpha >= beta {
+                self.trace(|tree| tree.set_outcome(Outcome::Draw));
+                return Some(alpha);
+            }
+        }
+
         let init_alpha = alpha;
-        let tt_entry = self.tt.load(board.get().hash());
-        if let Some(tt_entry) = tt_entry {
+        let tt_entry = self.tt.load(board.get().hash(), ply);
+        stat!(self, tt_probes);
+        stat!(self, tt_hits, tt_entry.is_some());
+        if let Some(tt_entry) = tt_entry.filter(|_| excluded.is_none()) {
             let should_cutoff = !is_pv && tt_entry.depth as i32 >= depth && match tt_entry.bound {
                 TtBound::Exact => true,
                 TtBound::Lower => tt_entry.score >= beta,
                 TtBound::Upper => tt_entry.score <= alpha,
             };
             if should_cutoff {
+                stat!(self, tt_cutoffs);
+                self.trace(|tree| tree.set_outcome(Outcome::TtCutoff));
                 return Some(tt_entry.score);
             }
         }
 
-        let static_eval = evaluate(board.get());
-        
-        if !is_pv && depth <= 4 {
-            let rfp_margin = depth as i16 * 80;
+        // Internal iterative reduction: without a TT move this node is likely to be
+        // badly ordered, so search it shallower and let the next iteration fill the TT.
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
+        if ply != 0 && tt_move.is_none() && excluded.is_none() && (is_pv || cut_node) && depth >= params.iir_min_depth {
+            depth -= 1;
+        }
+
+        let in_check = !board.get().checkers().is_empty();
+        let raw_eval = evaluate(board.get());
+        let static_eval = self.history.correct_eval(board.get(), raw_eval);
+        self.trace(|tree| tree.set_static_eval(static_eval));
+
+        // Static evals are only recorded outside of check, where they mean something.
+        self.stack[ply].static_eval = (!in_check).then_some(static_eval);
+        let improving = match ply.checked_sub(2) {
+            Some(prev_ply) => match self.stack[prev_ply].static_eval {
+                Some(prev_eval) => !in_check && static_eval > prev_eval,
+                None => !in_check,
+            },
+            None => false,
+        };
+
+        if !is_pv && !in_check && excluded.is_none() && depth <= params.rfp_max_depth && !beta.is_mate() {
+            let rfp_margin = ((depth - improving as i32) * params.rfp_margin) as i16;
             if static_eval - rfp_margin >= beta {
+                stat!(self, rfp);
+                self.trace(|tree| tree.set_outcome(Outcome::ReverseFutility));
                 return Some(static_eval - rfp_margin);
             }
         }
 
+        let razor_margin = (params.razor_margin * depth) as i16;
+        if !is_pv && !in_check && excluded.is_none() && depth <= params.razor_max_depth && static_eval + razor_margin <= alpha {
+            self.trace(|tree| tree.set_edge(Edge::Razoring, 0, 0));
+            let score = self.qsearch(board, alpha, alpha + 1, ply, true);
+            if score <= alpha {
+                stat!(self, razoring);
+                self.trace(|tree| tree.set_outcome(Outcome::Razoring));
+                return Some(score);
+            }
+        }
+
         let kings = board.get().pieces(Piece::King);
         let pawns = board.get().pieces(Piece::Pawn);
         let only_pawns = board.get().occupied() == kings | pawns;
-        if !is_pv && !only_pawns && depth >= 2 && static_eval >= beta && board.null_move() {
-            let reduction = 2 + (static_eval as i32 - beta as i32) / 200;
-            let score = -self.negamax(board, -beta, -beta + 1, depth - 1 - reduction, ply + 1)?;
+        let nmp_allowed = ply >= self.nmp_min_ply || board.get().side_to_move() != self.nmp_color;
+        let mut threat = None;
+        let can_null_move = !is_pv && excluded.is_none() && !only_pawns && nmp_allowed && !beta.is_mate();
+        if can_null_move && depth >= params.nmp_min_depth && static_eval >= beta && board.null_move() {
+            stat!(self, nmp_attempts);
+            self.stack.set_move(ply, None, false);
+            let reduction = params.nmp_base_reduction + (static_eval - beta) / params.nmp_eval_divisor;
+            self.trace(|tree| tree.set_edge(Edge::NullMove, reduction, 0));
+            let score = -self.negamax(board, -beta, -beta + 1, depth - 1 - reduction, ply + 1, !cut_node)?;
+            // Passing can't prove a mate, so don't return one. Getting mated after passing
+            // is a real threat though, and is left to fail low.
+            let score = match score >= beta && score.is_mate() {
+                true => beta,
+                false => score,
+            };
+
+            // The move that refuted passing is a threat we'll likely need to answer.
+            if score < beta {
+                threat = self.tt.load(board.get().hash(), ply + 1).and_then(|entry| entry.best_move);
+            }
             board.undo();
 
-            if score >= beta {
+            // Verification is skipped when the reduced search would be too shallow to mean
+            // anything, and never nested inside another verification search.
+            let verify_depth = depth - 1 - reduction;
+            let skip_verification = depth < params.nmp_verify_min_depth || verify_depth <= 0 || self.nmp_min_ply != 0;
+            if score >= beta && skip_verification {
+                stat!(self, nmp_cutoffs);
+                self.trace(|tree| tree.set_outcome(Outcome::NullMove));
                 return Some(score);
             }
+
+            // At high depth, guard against zugzwang by verifying the fail high with a reduced
+            // search of our own moves, with null moves disabled for us in the first part of it.
+            if score >= beta {
+                self.nmp_min_ply = ply + (3 * verify_depth / 4) as u16;
+                self.nmp_color = board.get().side_to_move();
+                self.trace(|tree| tree.set_edge(Edge::NullVerification, reduction, 0));
+                let verified = self.negamax(board, beta - 1, beta, verify_depth, ply, false);
+                self.nmp_min_ply = 0;
+                if verified? >= beta {
+                    stat!(self, nmp_cutoffs);
+                    self.trace(|tree| tree.set_outcome(Outcome::NullMove));
+                    return Some(score);
+                }
+            }
+        }
+
+        // ProbCut: a good capture that beats beta by a wide margin in a shallow search
+        // will very likely beat beta in the full search as well.
+        let probcut_beta = beta + params.probcut_margin as i16;
+        let probcut_depth = depth - params.probcut_reduction;
+        let tt_rules_out_probcut = tt_entry.is_some_and(|entry| {
+            entry.depth as i32 > probcut_depth && entry.score < probcut_beta
+        });
+        if cut_node && !in_check && excluded.is_none() && depth >= params.probcut_min_depth && !probcut_beta.is_mate() && !tt_rules_out_probcut {
+            let mut picker = MovePicker::new(tt_move, [None; 2], None, PickerMode::Captures);
+            while let Some(mv) = picker.next(board.get(), self.history) {
+                let see_threshold = (probcut_beta - static_eval).clamp(i16::MIN as i32, i16::MAX as i32);
+                if !see(board.get(), mv, see_threshold as i16) {
+                    continue;
+                }
+
+                self.stack.set_move(ply, Some(mv), move_is_capture(board.get(), mv));
+                board.play_unchecked(mv);
+                self.trace(|tree| tree.set_edge(Edge::Move(mv), 0, 0));
+                let mut score = -self.qsearch(board, -probcut_beta, -probcut_beta + 1, ply + 1, false);
+                if score >= probcut_beta {
+                    self.trace(|tree| tree.set_edge(Edge::Move(mv), params.probcut_reduction - 1, 0));
+                    score = -self.negamax(board, -probcut_beta, -probcut_beta + 1, probcut_depth, ply + 1, !cut_node)?;
+                }
+                board.undo();
+
+                if score >= probcut_beta {
+                    self.tt.store(board.get().hash(), ply, TtEntry {
+                        best_move: Some(mv),
+                        depth: (probcut_depth + 1).max(0) as u8,
+                        score,
+                        bound: TtBound::Lower,
+                    });
+                    stat!(self, probcut);
+                    self.trace(|tree| tree.set_outcome(Outcome::ProbCut));
+                    return Some(score);
+                }
+            }
         }
 
-        let mut quiets_to_check = if !is_pv {
-            match depth {
-                1 => 10,
-                2 => 13,
-                3 => 16,
-                4 => 19,
-                _ => i32::MAX,
+        let mut quiets_to_check = match depth {
+            _ if !is_pv && depth <= params.lmp_max_depth => {
+                params.lmp_base + depth * (params.lmp_scale + improving as i32 * params.lmp_improving_scale)
             }
-        } else {
-            i32::MAX
+            _ => i32::MAX,
         };
 
+        // Singular extension: if every alternative to the TT move fails low against
+        // a margin below the TT score, the TT move is the only good move, so extend it.
+        // If instead an alternative also beats beta, the node is very likely a cutoff.
+        let mut singular_extension = 0;
+        if let (Some(entry), Some(mv)) = (tt_entry, tt_move) {
+            let can_be_singular = ply != 0
+                && self.can_extend(ply)
+                && excluded.is_none()
+                && depth >= params.se_min_depth
+                && entry.depth as i32 >= depth - params.se_tt_depth_margin
+                && matches!(entry.bound, TtBound::Lower | TtBound::Exact)
+                && !entry.score.is_mate();
+            if can_be_singular {
+                let singular_beta = entry.score - (depth * params.se_beta_scale) as i16;
+                let singular_depth = (depth - 1) / 2;
+                self.trace(|tree| tree.set_edge(Edge::Singular, depth - 1 - singular_depth, 0));
+                self.stack[ply].excluded = Some(mv);
+                let score = self.negamax(board, singular_beta - 1, singular_beta, singular_depth, ply, cut_node);
+                self.stack[ply].excluded = None;
+                let score = score?;
+                if score < singular_beta {
+                    singular_extension = 1;
+                } else if singular_beta >= beta {
+                    self.trace(|tree| tree.set_outcome(Outcome::MultiCut));
+                    return Some(singular_beta);
+                }
+            }
+        }
+
         let mut best_move = None;
-        let mut best_score = -INFINITY;
-        let movelist = get_ordered_moves(board.get(), tt_entry, self.history, false);
-        for (i, &mv) in movelist.iter().enumerate() {
+        let mut best_score = -Score::INFINITY;
+        let mut quiets_tried = ArrayVec::<Move, 218>::new();
+        let mut captures_tried = ArrayVec::<Move, 218>::new();
+        let tt_move_is_capture = tt_move.is_some_and(|mv| move_is_capture(board.get(), mv));
+        let threatened = threat
+            .map(|threat| threat.to)
+            .filter(|&square| board.get().colors(board.get().side_to_move()).has(square));
+        let mut picker = MovePicker::new(tt_move, self.stack[ply].killers, threatened, PickerMode::All);
+        for i in 0.. {
+            let next = match ply {
+                0 => self.root_moves.get(i),
+                _ => picker.next(board.get(), self.history),
+            };
+            let Some(mv) = next else {
+                break;
+            };
+            if Some(mv) == excluded {
+                continue;
+            }
+
             let is_capture = move_is_capture(board.get(), mv);
-            let mut reduction = (i as i32 * 10 + depth * 15) / 100;
-            reduction -= self.history.get_quiet_score(board.get(), mv) / 200;
-            if reduction < 0 || is_capture {
-                reduction = 0;
+            let stm = board.get().side_to_move();
+            let is_recapture = is_pv && is_capture && ply.checked_sub(1).is_some_and(|prev_ply| {
+                let prev = &self.stack[prev_ply];
+                prev.is_capture && prev.move_played.is_some_and(|prev_mv| prev_mv.to == mv.to)
+            });
+            let is_seventh_rank_push = board.get().piece_on(mv.from) == Some(Piece::Pawn)
+                && mv.to.rank() == Rank::Seventh.relative_to(stm);
+            let extension = match () {
+                _ if Some(mv) == tt_move && singular_extension != 0 => singular_extension,
+                _ if (is_recapture || is_seventh_rank_push) && self.can_extend(ply) => 1,
+                _ => 0,
+            };
+            let history_score = match is_capture {
+                true => self.history.get_capture_score(board.get(), mv),
+                false => self.history.get_quiet_score(board.get(), mv),
+            };
+            let mut lmr = self.lmr_table[(depth as usize).min(LMR_TABLE_SIZE - 1)][(i + 1).min(LMR_TABLE_SIZE - 1)];
+            lmr -= is_pv as i32 * params.lmr_pv;
+            lmr += cut_node as i32 * params.lmr_cut_node;
+            lmr += !improving as i32 * params.lmr_improving;
+            lmr += (tt_move_is_capture && !is_capture) as i32 * params.lmr_tt_capture;
+            lmr -= history_score * LMR_GRAIN / params.lmr_history_divisor;
+            lmr -= (Some(mv.from) == threatened) as i32 * params.lmr_threat_escape;
+            // Captures are only reduced once they have a history of failing.
+            if is_capture && history_score >= 0 {
+                lmr = 0;
             }
 
-            if i != 0 && !is_capture {
+            let gives_check = gives_check(board.get(), mv);
+            if i != 0 && !is_capture && !gives_check {
                 if quiets_to_check == 0 {
-                    break;
+                    stat!(self, lmp);
+                    self.trace(|tree| tree.pruned(mv, lmr.max(0) / LMR_GRAIN, Outcome::LateMovePruned));
+                    picker.skip_quiets();
+                    continue;
                 }
                 quiets_to_check -= 1;
             }
 
-            let mut score = -INFINITY;
+            // Never in PV nodes, so every root move gets a fresh score each iteration.
+            let futility_margin = (params.fp_base + depth * params.fp_scale) as i16;
+            if !is_pv && i != 0 && !is_capture && !gives_check && !in_check && depth <= params.fp_max_depth && static_eval + futility_margin <= alpha {
+                stat!(self, futility);
+                self.trace(|tree| tree.pruned(mv, lmr.max(0) / LMR_GRAIN, Outcome::FutilityPruned));
+                picker.skip_quiets();
+                continue;
+            }
+
+            if !is_pv && i != 0 && depth <= params.see_max_depth {
+                let see_margin = match is_capture {
+                    true => -params.see_capture_margin * depth * depth,
+                    false => -params.see_quiet_margin * depth,
+                };
+                if !see(board.get(), mv, see_margin as i16) {
+                    stat!(self, see_pruning);
+                    self.trace(|tree| tree.pruned(mv, lmr.max(0) / LMR_GRAIN, Outcome::SeePruned));
+                    continue;
+                }
+            }
+
+            let mut score = -Score::INFINITY;
+            let nodes_before = self.nodes;
+            self.stack.set_move(ply, Some(mv), is_capture);
             board.play_unchecked(mv);
 
+            lmr -= gives_check as i32 * params.lmr_check;
+            let new_depth = depth - 1 + extension;
+            let reduction = (lmr / LMR_GRAIN).clamp(0, (new_depth - 1).max(0));
+
             if i != 0 {
-                score = -self.negamax(board, -alpha - 1, -alpha, depth - 1 - reduction, ply + 1)?;
+                stat!(self, lmr_searches, reduction != 0);
+                self.trace(|tree| tree.set_edge(Edge::Move(mv), reduction, extension));
+                score = -self.negamax(board, -alpha - 1, -alpha, new_depth - reduction, ply + 1, true)?;
             }
             
             if i != 0 && reduction != 0 && score > alpha {
-                score = -self.negamax(board, -alpha - 1, -alpha, depth - 1, ply + 1)?;
+                stat!(self, lmr_researches);
+                self.trace(|tree| tree.set_edge(Edge::Move(mv), 0, extension));
+                score = -self.negamax(board, -alpha - 1, -alpha, new_depth, ply + 1, !cut_node)?;
             }
             
             if i == 0 || score > alpha {
-                score = -self.negamax(board, -beta, -alpha, depth - 1, ply + 1)?;
+                self.trace(|tree| tree.set_edge(Edge::Move(mv), 0, extension));
+                score = -self.negamax(board, -beta, -alpha, new_depth, ply + 1, !is_pv && !cut_node)?;
             }
 
             board.undo();
 
+            if ply == 0 {
+                self.root_moves.update(mv, score, score > alpha, self.nodes - nodes_before);
+            }
+
             if score > best_score {
                 best_move = Some(mv);
                 best_score = score;
+                if is_pv && score > alpha {
+                    self.stack.update_pv(ply, mv);
+                }
                 alpha = alpha.max(score);
             }
 
             if score >= beta {
-                if !is_capture {
-                    let change = depth as i32 * depth as i32;
-                    for &mv in &movelist[..i] {
-                        if !move_is_capture(board.get(), mv) {
-                            self.history.update_move(board.get(), mv, -change);
-                        }
+                stat!(self, beta_cutoffs);
+                stat!(self, first_move_cutoffs, i == 0);
+                stat!(self, cutoff_index_sum, i);
+                let change = depth * depth;
+                for &mv in &captures_tried {
+                    self.history.update_capture(board.get(), mv, -change, params.max_history);
+                }
+                if is_capture {
+                    self.history.update_capture(board.get(), mv, change, params.max_history);
+                } else {
+                    for &mv in &quiets_tried {
+                        self.history.update_move(board.get(), mv, -change, params.max_history);
+                    }
+                    self.history.update_move(board.get(), mv, change, params.max_history);
+
+                    let killers = &mut self.stack[ply].killers;
+                    if killers[0] != Some(mv) {
+                        killers[1] = killers[0];
+                        killers[0] = Some(mv);
                     }
-                    self.history.update_move(board.get(), mv, change);
                 }
 
                 break;
             }
+
+            match is_capture {
+                true => captures_tried.push(mv),
+                false => quiets_tried.push(mv),
+            }
         }
 
-        let best_move = best_move.expect("missing best move?");
+        let Some(best_move) = best_move else {
+            // Only a singular verification search can run out of moves,
+            // since the first move is never pruned otherwise.
+            assert!(excluded.is_some(), "missing best move?");
+            return Some(alpha);
+        };
         if ply == 0 {
             self.best_move = Some(best_move);
         }
+        if excluded.is_some() {
+            return Some(best_score);
+        }
+
+        // Learn from nodes where the search result disagrees with the static eval in a way
+        // the bound can confirm. Tactical nodes say more about the capture than the position.
+        let bound = match () {
+            _ if alpha >= beta => TtBound::Lower,
+            _ if alpha > init_alpha => TtBound::Exact,
+            _ => TtBound::Upper,
+        };
+        let best_is_capture = move_is_capture(board.get(), best_move);
+        let should_correct = !in_check
+            && !best_is_capture
+            && !best_score.is_mate()
+            && match bound {
+                TtBound::Lower => best_score > static_eval,
+                TtBound::Upper => best_score < static_eval,
+                TtBound::Exact => true,
+            };
+        if should_correct {
+            let error = best_score - raw_eval;
+            self.history.update_correction(board.get(), depth, error);
+        }
 
-        // TODO mate correction
-        self.tt.store(board.get().hash(), TtEntry {
+        self.tt.store(board.get().hash(), ply, TtEntry {
             best_move: match alpha > init_alpha {
                 true => Some(best_move),
                 false => tt_entry.and_then(|entry| entry.best_move),
             },
             depth: depth.clamp(0, u8::MAX as i32) as u8,
             score: best_score,
-            bound: match () {
-                _ if alpha >= beta => TtBound::Lower,
-                _ if alpha > init_alpha => TtBound::Exact,
-                _ => TtBound::Upper,
-            }
+            bound,
         });
         Some(best_score)
     }
 
-    fn qsearch(&mut self, board: &mut BoardStack, mut alpha: i16, beta: i16, ply: u16) -> i16 {
-        assert!((-INFINITY..=INFINITY).contains(&alpha));
-        assert!((-INFINITY..=INFINITY).contains(&beta));
+    fn qsearch(&mut self, board: &mut BoardStack, alpha: Score, beta: Score, ply: u16, checks: bool) -> Score {
+        self.trace(|tree| tree.enter(ply, 0, alpha, beta, true));
+        let score = self.qsearch_node(board, alpha, beta, ply, checks);
+        self.trace(|tree| tree.exit(Some(score)));
+        score
+    }
+
+    /// With `checks`, quiet checks are searched along with captures. This is only done on the
+    /// first ply of the quiescence search, so that it can't run on indefinitely.
+    fn qsearch_node(&mut self, board: &mut BoardStack, mut alpha: Score, beta: Score, ply: u16, checks: bool) -> Score {
+        assert!((-Score::INFINITY..=Score::INFINITY).contains(&alpha));
+        assert!((-Score::INFINITY..=Score::INFINITY).contains(&beta));
         assert!(alpha < beta);
+        self.stack[ply].pv.clear();
 
         self.nodes += 1;
+        stat!(self, qsearch_nodes);
+
+        if board.is_draw() {
+            self.trace(|tree| tree.set_outcome(Outcome::Draw));
+            return Score::DRAW;
+        }
 
         match board.get().status() {
-            GameStatus::Won => return -CHECKMATE + ply as i16,
-            GameStatus::Drawn => return 0,
+            GameStatus::Won => {
+                self.trace(|tree| tree.set_outcome(Outcome::Terminal));
+                return Score::mated_in(ply);
+            }
+            GameStatus::Drawn => {
+                self.trace(|tree| tree.set_outcome(Outcome::Terminal));
+                return Score::DRAW;
+            }
             GameStatus::Ongoing => {},
         }
 
-        let tt_entry = self.tt.load(board.get().hash());
+        if ply >= MAX_PLY {
+            return evaluate(board.get());
+        }
+
+        let init_alpha = alpha;
+        let tt_entry = self.tt.load(board.get().hash(), ply);
+        stat!(self, tt_probes);
+        stat!(self, tt_hits, tt_entry.is_some());
+        if let Some(tt_entry) = tt_entry {
+            let should_cutoff = match tt_entry.bound {
+                TtBound::Exact => true,
+                TtBound::Lower => tt_entry.score >= beta,
+                TtBound::Upper => tt_entry.score <= alpha,
+            };
+            if should_cutoff {
+                stat!(self, tt_cutoffs);
+                self.trace(|tree| tree.set_outcome(Outcome::TtCutoff));
+                return tt_entry.score;
+            }
+        }
 
-        let mut best_score = evaluate(board.get());
+        // In check there is no standing pat: every evasion is searched,
+        // and if none of them are any good we are mated.
+        let in_check = !board.get().checkers().is_empty();
+        let stand_pat = evaluate(board.get());
+        self.trace(|tree| tree.set_static_eval(stand_pat));
+        let mut best_score = match in_check {
+            true => Score::mated_in(ply),
+            false => stand_pat,
+        };
         alpha = alpha.max(best_score);
         if best_score >= beta {
+            self.trace(|tree| tree.set_outcome(Outcome::StandPat));
             return best_score;
         }
 
-        for mv in get_ordered_moves(board.get(), tt_entry, self.history, true) {
+        let mut best_move = None;
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
+        let mode = match () {
+            _ if in_check => PickerMode::All,
+            _ if checks => PickerMode::CapturesAndChecks,
+            _ => PickerMode::Captures,
+        };
+        let mut picker = MovePicker::new(tt_move, [None; 2], None, mode);
+        while let Some(mv) = picker.next(board.get(), self.history) {
+            // Delta pruning: skip captures that can't raise alpha even with a margin to spare.
+            if !in_check && mv.promotion.is_none() {
+                if let Some(victim) = captured_piece(board.get(), mv) {
+                    if stand_pat + SEE_VALUES[victim as usize] + self.params.delta_margin as i16 <= alpha {
+                        self.trace(|tree| tree.pruned(mv, 0, Outcome::DeltaPruned));
+                        continue;
+                    }
+                }
+            }
+
             board.play_unchecked(mv);
-            let child_score = -self.qsearch(board, -beta, -alpha, ply + 1);
+            self.trace(|tree| tree.set_edge(Edge::Move(mv), 0, 0));
+            let child_score = -self.qsearch(board, -beta, -alpha, ply + 1, false);
             board.undo();
 
             if child_score > best_score {
+                best_move = Some(mv);
                 best_score = child_score;
                 alpha = alpha.max(child_score);
             }
@@ -282,6 +729,65 @@ impl<'s> Search<'s> {
             }
         }
 
+        self.tt.store(board.get().hash(), ply, TtEntry {
+            best_move: best_move.or(tt_move),
+            depth: 0,
+            score: best_score,
+            bound: match () {
+                _ if alpha >= beta => TtBound::Lower,
+                _ if alpha > init_alpha => TtBound::Exact,
+                _ => TtBound::Upper,
+            },
+        });
         best_score
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(board: &Board, depth: u8) -> SearchInfo {
+        let mut tt = TranspositionTable::new(1024 * 1024);
+        let mut history = HistoryTables::new();
+        let params = SearchParams::default();
+        let mut result = None;
+        let search = Search::new(&mut tt, &mut history, &params, SearchLimits::PerMove { depth }, Duration::ZERO, None);
+        search.start(board, &[], &mut |info| result = Some(info));
+        result.expect("missing search result?")
+    }
+
+    #[test]
+    fn answers_quiet_mate_threat() {
+        // Black is a queen up, but Re8 is mate unless the back rank is covered.
+        let board = "6k1/5ppp/8/8/8/8/q4PPP/4R1K1 b - - 0 1".parse::<Board>().unwrap();
+        let info = search(&board, 6);
+        let mut after = board.clone();
+        after.play(info.best_move);
+        let mut allows_mate = false;
+        after.generate_moves(|moves| {
+            for mv in moves {
+                let mut child = after.clone();
+                child.play_unchecked(mv);
+                allows_mate |= child.status() == GameStatus::Won;
+            }
+            allows_mate
+        });
+        assert!(!allows_mate, "{} allows mate", info.best_move);
+        assert!(info.eval > Score::DRAW);
+    }
+
+    #[test]
+    fn null_move_respects_mate_threat() {
+        // Black is lost to an unstoppable Rb8#, despite looking fine when allowed to pass.
+        let board = "7k/R7/8/8/8/8/8/1R4K1 b - - 0 1".parse::<Board>().unwrap();
+        let mut tt = TranspositionTable::new(1024 * 1024);
+        let mut history = HistoryTables::new();
+        let params = SearchParams::default();
+        let mut search = Search::new(&mut tt, &mut history, &params, SearchLimits::PerMove { depth: 8 }, Duration::ZERO, None);
+        // Far enough below the static eval that White won't just pass back.
+        let beta = evaluate(&board) - 500;
+        let score = search.negamax(&mut BoardStack::new(&board, &[]), beta - 1, beta, 8, 1, true);
+        assert_eq!(score, Some(Score::mated_in(3)));
+    }
+}
@@ -0,0 +1,66 @@
+use std::ops::{Index, IndexMut};
+
+use arrayvec::ArrayVec;
+use cozy_chess::Move;
+
+use super::score::Score;
+
+pub const MAX_PLY: u16 = 128;
+
+/// State for one ply of the current search path, kept for the plies after it to look back on.
+#[derive(Debug, Clone, Default)]
+pub struct StackFrame {
+    /// The static eval of the position, if it was searched outside of check.
+    pub static_eval: Option<Score>,
+    pub killers: [Option<Move>; 2],
+    /// A move skipped by a singular extension search of this position.
+    pub excluded: Option<Move>,
+    /// The move being searched from this position, or `None` for a null move.
+    pub move_played: Option<Move>,
+    pub is_capture: bool,
+    /// The principal variation from this position, as of the last move to raise alpha.
+    pub pv: ArrayVec<Move, { MAX_PLY as usize }>,
+}
+
+/// A frame for every ply of the search, shared across the recursion.
+pub struct SearchStack {
+    frames: Box<[StackFrame]>,
+}
+
+impl SearchStack {
+    pub fn new() -> Self {
+        Self {
+            frames: (0..=MAX_PLY).map(|_| StackFrame::default()).collect(),
+        }
+    }
+
+    /// Records the move played from `ply` along with the line that followed it.
+    pub fn update_pv(&mut self, ply: u16, mv: Move) {
+        let (frames, children) = self.frames.split_at_mut(ply as usize + 1);
+        let pv = &mut frames[ply as usize].pv;
+        pv.clear();
+        pv.push(mv);
+        pv.extend(children[0].pv.iter().copied());
+    }
+
+    /// Marks the move searched from `ply` for the plies below it.
+    pub fn set_move(&mut self, ply: u16, mv: Option<Move>, is_capture: bool) {
+        let frame = &mut self[ply];
+        frame.move_played = mv;
+        frame.is_capture = is_capture;
+    }
+}
+
+impl Index<u16> for SearchStack {
+    type Output = StackFrame;
+
+    fn index(&self, ply: u16) -> &StackFrame {
+        &self.frames[ply as usize]
+    }
+}
+
+impl IndexMut<u16> for SearchStack {
+    fn index_mut(&mut self, ply: u16) -> &mut StackFrame {
+        &mut self.frames[ply as usize]
+    }
+}
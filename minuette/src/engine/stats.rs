@@ -0,0 +1,94 @@
+use std::fmt;
+
+/// Counters describing how the search behaved.
+/// They are only collected with the `stats` feature, and are all zero otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub qsearch_nodes: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    pub cutoff_index_sum: u64,
+    pub rfp: u64,
+    pub razoring: u64,
+    pub nmp_attempts: u64,
+    pub nmp_cutoffs: u64,
+    pub probcut: u64,
+    pub lmp: u64,
+    pub futility: u64,
+    pub see_pruning: u64,
+    pub lmr_searches: u64,
+    pub lmr_researches: u64,
+}
+
+impl SearchStats {
+    pub const ENABLED: bool = cfg!(feature = "stats");
+
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.qsearch_nodes += other.qsearch_nodes;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.tt_cutoffs += other.tt_cutoffs;
+        self.beta_cutoffs += other.beta_cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.cutoff_index_sum += other.cutoff_index_sum;
+        self.rfp += other.rfp;
+        self.razoring += other.razoring;
+        self.nmp_attempts += other.nmp_attempts;
+        self.nmp_cutoffs += other.nmp_cutoffs;
+        self.probcut += other.probcut;
+        self.lmp += other.lmp;
+        self.futility += other.futility;
+        self.see_pruning += other.see_pruning;
+        self.lmr_searches += other.lmr_searches;
+        self.lmr_researches += other.lmr_researches;
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    part as f64 * 100.0 / total.max(1) as f64
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tt_hit {:.1}% tt_cutoff {:.1}% first_move_cutoff {:.1}% avg_cutoff_index {:.2} \
+            rfp {} razoring {} nmp {}/{} probcut {} lmp {} futility {} see {} \
+            lmr_research {:.1}% qsearch_nodes {:.1}%",
+            percent(self.tt_hits, self.tt_probes),
+            percent(self.tt_cutoffs, self.tt_probes),
+            percent(self.first_move_cutoffs, self.beta_cutoffs),
+            self.cutoff_index_sum as f64 / self.beta_cutoffs.max(1) as f64,
+            self.rfp,
+            self.razoring,
+            self.nmp_cutoffs,
+            self.nmp_attempts,
+            self.probcut,
+            self.lmp,
+            self.futility,
+            self.see_pruning,
+            percent(self.lmr_researches, self.lmr_searches),
+            percent(self.qsearch_nodes, self.nodes),
+        )
+    }
+}
+
+/// Bumps a [`SearchStats`] counter on a search, compiling to nothing without the `stats` feature.
+macro_rules! stat {
+    ($search:expr, $counter:ident) => {
+        stat!($search, $counter, 1)
+    };
+    ($search:expr, $counter:ident, $amount:expr) => {
+        #[cfg(feature = "stats")]
+        {
+            $search.stats.$counter += $amount as u64;
+        }
+    };
+}
+
+pub(crate) use stat;
@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use cozy_chess::Move;
+
+use super::search::SearchLimits;
+use super::score::Score;
+
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+const MAX_MOVES_TO_GO: u32 = 50;
+const STABILITY_SCALE: [f64; 5] = [2.0, 1.4, 1.1, 0.9, 0.8];
+
+pub struct TimeManager {
+    search_start: Instant,
+    base_soft_limit: Duration,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    prev_best_move: Option<Move>,
+    prev_score: Option<Score>,
+    stability: usize,
+}
+
+impl TimeManager {
+    pub fn new(limits: SearchLimits, move_overhead: Duration) -> Self {
+        let (soft_limit, hard_limit) = match limits {
+            SearchLimits::PerGame { clock, increment, moves_to_go } => {
+                allocate(clock, increment, moves_to_go, move_overhead)
+            }
+            SearchLimits::PerMove { .. } => (Duration::MAX, Duration::MAX),
+        };
+
+        Self {
+            search_start: Instant::now(),
+            base_soft_limit: soft_limit,
+            soft_limit,
+            hard_limit,
+            prev_best_move: None,
+            prev_score: None,
+            stability: 0,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.search_start.elapsed()
+    }
+
+    /// Whether the search must be abandoned, even mid-iteration.
+    pub fn hard_limit_reached(&self) -> bool {
+        self.elapsed() >= self.hard_limit
+    }
+
+    /// Whether another iteration is unlikely to finish or change the result.
+    pub fn soft_limit_reached(&self) -> bool {
+        self.elapsed() >= self.soft_limit
+    }
+
+    /// Rescales the soft limit after a completed iteration.
+    /// Time is saved when the best move is stable and took most of the effort,
+    /// and spent when the best move keeps changing or the score is dropping.
+    pub fn update(&mut self, best_move: Move, score: Score, best_move_nodes: u64, total_nodes: u64) {
+        self.stability = match self.prev_best_move == Some(best_move) {
+            true => (self.stability + 1).min(STABILITY_SCALE.len() - 1),
+            false => 0,
+        };
+        let stability_scale = STABILITY_SCALE[self.stability];
+
+        let score_drop = self.prev_score.map_or(0, |prev| prev - score);
+        let score_scale = 1.0 + score_drop.clamp(0, 100) as f64 / 200.0;
+
+        let best_move_fraction = best_move_nodes as f64 / total_nodes.max(1) as f64;
+        let nodes_scale = (1.5 - best_move_fraction) * 1.35;
+
+        self.prev_best_move = Some(best_move);
+        self.prev_score = Some(score);
+        if self.base_soft_limit != Duration::MAX {
+            let scale = stability_scale * score_scale * nodes_scale;
+            self.soft_limit = self.base_soft_limit.mul_f64(scale).min(self.hard_limit);
+        }
+    }
+}
+
+/// Splits the remaining clock into a soft and a hard limit for this move.
+/// Neither ever exceeds the clock minus the move overhead,
+/// since the increment only arrives after the move is made.
+fn allocate(clock: Duration, increment: Duration, moves_to_go: Option<u32>, move_overhead: Duration) -> (Duration, Duration) {
+    let available = clock.saturating_sub(move_overhead);
+    let moves_to_go = moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, MAX_MOVES_TO_GO);
+
+    let base = available / moves_to_go + increment * 3 / 4;
+    let hard_limit = (base * 3).min(available * 3 / 4);
+    let soft_limit = base.min(hard_limit);
+    (soft_limit, hard_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn per_game(clock: u64, increment: u64, moves_to_go: Option<u32>) -> SearchLimits {
+        SearchLimits::PerGame {
+            clock: Duration::from_millis(clock),
+            increment: Duration::from_millis(increment),
+            moves_to_go,
+        }
+    }
+
+    #[test]
+    fn never_exceeds_clock() {
+        let overhead = Duration::from_millis(10);
+        for clock in [0, 1, 5, 10, 11, 50, 100, 1000, 60_000] {
+            for increment in [0, 10, 100, 1000, 10_000] {
+                for moves_to_go in [None, Some(1), Some(2), Some(40)] {
+                    let tm = TimeManager::new(per_game(clock, increment, moves_to_go), overhead);
+                    let available = Duration::from_millis(clock).saturating_sub(overhead);
+                    assert!(tm.hard_limit <= available, "{} {} {:?}", clock, increment, moves_to_go);
+                    assert!(tm.soft_limit <= tm.hard_limit, "{} {} {:?}", clock, increment, moves_to_go);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clock_below_overhead() {
+        let tm = TimeManager::new(per_game(5, 1000, None), Duration::from_millis(10));
+        assert_eq!(tm.soft_limit, Duration::ZERO);
+        assert_eq!(tm.hard_limit, Duration::ZERO);
+        assert!(tm.hard_limit_reached());
+    }
+
+    #[test]
+    fn low_clock_with_increment() {
+        let tm = TimeManager::new(per_game(200, 1000, None), Duration::from_millis(10));
+        assert!(tm.hard_limit <= Duration::from_millis(190));
+        assert!(tm.soft_limit > Duration::ZERO);
+    }
+
+    #[test]
+    fn increment_and_moves_to_go_add_time() {
+        let overhead = Duration::ZERO;
+        let sudden_death = TimeManager::new(per_game(60_000, 0, None), overhead);
+        let increment = TimeManager::new(per_game(60_000, 1000, None), overhead);
+        let last_move = TimeManager::new(per_game(60_000, 0, Some(1)), overhead);
+        assert!(increment.soft_limit > sudden_death.soft_limit);
+        assert!(last_move.soft_limit > sudden_death.soft_limit);
+    }
+
+    #[test]
+    fn per_move_is_unlimited() {
+        let tm = TimeManager::new(SearchLimits::PerMove { depth: 5 }, Duration::from_millis(10));
+        assert!(!tm.hard_limit_reached());
+        assert!(!tm.soft_limit_reached());
+    }
+
+    #[test]
+    fn soft_limit_follows_stability() {
+        let e2e4 = "e2e4".parse().unwrap();
+        let d2d4 = "d2d4".parse().unwrap();
+        let mut tm = TimeManager::new(per_game(60_000, 0, None), Duration::ZERO);
+
+        tm.update(e2e4, Score::new(20), 500, 1000);
+        let unstable = tm.soft_limit;
+        for _ in 0..4 {
+            tm.update(e2e4, Score::new(20), 500, 1000);
+        }
+        let stable = tm.soft_limit;
+        assert!(stable < unstable);
+
+        tm.update(d2d4, Score::new(20), 500, 1000);
+        assert!(tm.soft_limit > stable);
+    }
+
+    #[test]
+    fn soft_limit_follows_score_drops_and_effort() {
+        let e2e4 = "e2e4".parse().unwrap();
+        let mut tm = TimeManager::new(per_game(60_000, 0, None), Duration::ZERO);
+        tm.update(e2e4, Score::new(20), 500, 1000);
+        tm.update(e2e4, Score::new(20), 500, 1000);
+        let steady = tm.soft_limit;
+
+        tm.update(e2e4, Score::new(-60), 500, 1000);
+        let dropping = tm.soft_limit;
+        assert!(dropping > steady);
+
+        tm.update(e2e4, Score::new(-60), 950, 1000);
+        tm.update(e2e4, Score::new(-60), 950, 1000);
+        let focused = tm.soft_limit;
+        tm.update(e2e4, Score::new(-60), 200, 1000);
+        assert!(tm.soft_limit > focused);
+    }
+}
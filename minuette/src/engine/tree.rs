@@ -0,0 +1,310 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+
+use cozy_chess::Move;
+
+use super::score::Score;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeFormat {
+    Json,
+    Dot,
+}
+
+/// Where to dump the search tree of a `go`, and how much of it to record.
+#[derive(Debug, Clone)]
+pub struct TreeDumpConfig {
+    pub path: PathBuf,
+    pub format: TreeFormat,
+    pub max_ply: u16,
+    pub max_nodes: usize,
+}
+
+impl TreeDumpConfig {
+    /// Picks the format from the file extension, defaulting to JSON.
+    pub fn new(path: PathBuf, max_ply: u16, max_nodes: usize) -> Self {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dot" | "gv") => TreeFormat::Dot,
+            _ => TreeFormat::Json,
+        };
+        Self { path, format, max_ply, max_nodes }
+    }
+}
+
+/// How a node was reached from its parent.
+#[derive(Debug, Clone, Copy)]
+pub enum Edge {
+    Root,
+    Move(Move),
+    NullMove,
+    /// A singular extension verification search of the same position.
+    Singular,
+    /// A razoring verification qsearch of the same position.
+    Razoring,
+    /// A null move pruning verification search of the same position.
+    NullVerification,
+}
+
+/// Why a node returned the score it did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Searched,
+    Draw,
+    Terminal,
+    TtCutoff,
+    ReverseFutility,
+    Razoring,
+    NullMove,
+    ProbCut,
+    MultiCut,
+    StandPat,
+    /// A move skipped by late move pruning, along with any quiets after it.
+    LateMovePruned,
+    /// A move skipped by futility pruning, along with any quiets after it.
+    FutilityPruned,
+    SeePruned,
+    DeltaPruned,
+    Aborted,
+}
+
+#[derive(Debug, Clone)]
+struct TreeNode {
+    parent: Option<usize>,
+    edge: Edge,
+    ply: u16,
+    depth: i32,
+    qsearch: bool,
+    alpha: Score,
+    beta: Score,
+    static_eval: Option<Score>,
+    reduction: i32,
+    extension: i32,
+    outcome: Outcome,
+    score: Option<Score>,
+}
+
+/// Records the search tree of the last completed iteration, for debugging.
+pub struct SearchTree {
+    config: TreeDumpConfig,
+    nodes: Vec<TreeNode>,
+    completed: Vec<TreeNode>,
+    /// The recorded index of every node currently being searched,
+    /// or `None` for nodes past the recording limits.
+    open: Vec<Option<usize>>,
+    next_edge: (Edge, i32, i32),
+}
+
+impl SearchTree {
+    pub fn new(config: TreeDumpConfig) -> Self {
+        Self {
+            config,
+            nodes: Vec::new(),
+            completed: Vec::new(),
+            open: Vec::new(),
+            next_edge: (Edge::Root, 0, 0),
+        }
+    }
+
+    pub fn begin_iteration(&mut self) {
+        self.nodes.clear();
+        self.open.clear();
+    }
+
+    pub fn end_iteration(&mut self) {
+        std::mem::swap(&mut self.nodes, &mut self.completed);
+    }
+
+    /// Describes the next child searched from the current node.
+    pub fn set_edge(&mut self, edge: Edge, reduction: i32, extension: i32) {
+        self.next_edge = (edge, reduction, extension);
+    }
+
+    pub fn enter(&mut self, ply: u16, depth: i32, alpha: Score, beta: Score, qsearch: bool) {
+        let (edge, reduction, extension) = std::mem::replace(&mut self.next_edge, (Edge::Root, 0, 0));
+        let parent = self.open.last().copied();
+        let recorded = match parent {
+            Some(None) => false,
+            _ => ply <= self.config.max_ply && self.nodes.len() < self.config.max_nodes,
+        };
+        if !recorded {
+            self.open.push(None);
+            return;
+        }
+
+        self.open.push(Some(self.nodes.len()));
+        self.nodes.push(TreeNode {
+            parent: parent.flatten(),
+            edge,
+            ply,
+            depth,
+            qsearch,
+            alpha,
+            beta,
+            static_eval: None,
+            reduction,
+            extension,
+            outcome: Outcome::Searched,
+            score: None,
+        });
+    }
+
+    pub fn exit(&mut self, score: Option<Score>) {
+        let node = self.open.pop().expect("missing open node?");
+        if let Some(node) = node.map(|index| &mut self.nodes[index]) {
+            node.score = score;
+            if score.is_none() {
+                node.outcome = Outcome::Aborted;
+            }
+        }
+    }
+
+    /// Marks the current node as having dropped into qsearch.
+    pub fn set_qsearch(&mut self) {
+        if let Some(node) = self.current() {
+            node.qsearch = true;
+        }
+    }
+
+    fn current(&mut self) -> Option<&mut TreeNode> {
+        let index = (*self.open.last()?)?;
+        Some(&mut self.nodes[index])
+    }
+
+    pub fn set_static_eval(&mut self, static_eval: Score) {
+        if let Some(node) = self.current() {
+            node.static_eval = Some(static_eval);
+        }
+    }
+
+    pub fn set_outcome(&mut self, outcome: Outcome) {
+        if let Some(node) = self.current() {
+            node.outcome = outcome;
+        }
+    }
+
+    /// Records a move of the current node that was pruned without being searched.
+    pub fn pruned(&mut self, mv: Move, reduction: i32, outcome: Outcome) {
+        let Some(&Some(parent)) = self.open.last() else {
+            return;
+        };
+        let parent_node = &self.nodes[parent];
+        if parent_node.ply >= self.config.max_ply || self.nodes.len() >= self.config.max_nodes {
+            return;
+        }
+        self.nodes.push(TreeNode {
+            parent: Some(parent),
+            edge: Edge::Move(mv),
+            ply: parent_node.ply + 1,
+            depth: parent_node.depth - 1,
+            qsearch: parent_node.qsearch,
+            alpha: -parent_node.beta,
+            beta: -parent_node.alpha,
+            static_eval: None,
+            reduction,
+            extension: 0,
+            outcome,
+            score: None,
+        });
+    }
+
+    /// Writes the last completed iteration, or the partial one if none completed.
+    pub fn write(&self) -> io::Result<()> {
+        let nodes = match self.completed.is_empty() {
+            true => &self.nodes,
+            false => &self.completed,
+        };
+        let output = match self.config.format {
+            TreeFormat::Json => to_json(nodes),
+            TreeFormat::Dot => to_dot(nodes),
+        };
+        std::fs::write(&self.config.path, output)
+    }
+}
+
+fn edge_label(edge: Edge) -> String {
+    match edge {
+        Edge::Root => "root".to_owned(),
+        Edge::Move(mv) => mv.to_string(),
+        Edge::NullMove => "null".to_owned(),
+        Edge::Singular => "singular".to_owned(),
+        Edge::Razoring => "razoring".to_owned(),
+        Edge::NullVerification => "null verification".to_owned(),
+    }
+}
+
+fn optional(value: Option<Score>) -> String {
+    value.map_or("null".to_owned(), |value| value.get().to_string())
+}
+
+fn children(nodes: &[TreeNode]) -> Vec<Vec<usize>> {
+    let mut children = vec![Vec::new(); nodes.len()];
+    for (index, node) in nodes.iter().enumerate() {
+        if let Some(parent) = node.parent {
+            children[parent].push(index);
+        }
+    }
+    children
+}
+
+fn to_json(nodes: &[TreeNode]) -> String {
+    fn write_node(out: &mut String, nodes: &[TreeNode], children: &[Vec<usize>], index: usize) {
+        let node = &nodes[index];
+        write!(
+            out,
+            "{{\"move\":\"{}\",\"ply\":{},\"depth\":{},\"qsearch\":{},\"alpha\":{},\"beta\":{},\
+            \"static_eval\":{},\"reduction\":{},\"extension\":{},\"outcome\":\"{:?}\",\"score\":{},\"children\":[",
+            edge_label(node.edge),
+            node.ply,
+            node.depth,
+            node.qsearch,
+            node.alpha.get(),
+            node.beta.get(),
+            optional(node.static_eval),
+            node.reduction,
+            node.extension,
+            node.outcome,
+            optional(node.score),
+        ).unwrap();
+        for (i, &child) in children[index].iter().enumerate() {
+            if i != 0 {
+                out.push(',');
+            }
+            write_node(out, nodes, children, child);
+        }
+        out.push_str("]}");
+    }
+
+    let children = children(nodes);
+    let mut out = String::new();
+    match nodes.is_empty() {
+        true => out.push_str("null"),
+        false => write_node(&mut out, nodes, &children, 0),
+    }
+    out.push('\n');
+    out
+}
+
+fn to_dot(nodes: &[TreeNode]) -> String {
+    let mut out = String::from("digraph search {\n    node [shape=box, fontname=monospace];\n");
+    for (index, node) in nodes.iter().enumerate() {
+        writeln!(
+            out,
+            "    n{} [label=\"{} d{}{}\\n[{}, {}] eval {}\\n{:?} {}\"];",
+            index,
+            edge_label(node.edge),
+            node.depth,
+            if node.qsearch { " q" } else { "" },
+            node.alpha.get(),
+            node.beta.get(),
+            optional(node.static_eval),
+            node.outcome,
+            optional(node.score),
+        ).unwrap();
+        if let Some(parent) = node.parent {
+            writeln!(out, "    n{} -> n{} [label=\"r{} e{}\"];", parent, index, node.reduction, node.extension).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
@@ -1,5 +1,7 @@
 use cozy_chess::Move;
 
+use super::score::Score;
+
 #[derive(Debug, Clone, Copy)]
 pub enum TtBound {
     Upper,
@@ -11,7 +13,8 @@ pub enum TtBound {
 pub struct TtEntry {
     pub best_move: Option<Move>,
     pub depth: u8,
-    pub score: i16,
+    /// Mate scores are relative to the node, see [`Score::to_tt`].
+    pub score: Score,
     pub bound: TtBound,
 }
 
@@ -30,13 +33,28 @@ impl TranspositionTable {
         }
     }
 
-    pub fn load(&self, hash: u64) -> Option<TtEntry> {
+    /// Loads the entry for a position at `ply`, with any mate score made relative to the root.
+    pub fn load(&self, hash: u64, ply: u16) -> Option<TtEntry> {
         let (entry_hash, entry) = self.table[self.index(hash)]?;
-        (entry_hash == hash).then_some(entry)
+        (entry_hash == hash).then_some(TtEntry {
+            score: entry.score.to_search(ply),
+            ..entry
+        })
     }
 
-    pub fn store(&mut self, hash: u64, entry: TtEntry) {
+    /// Stores an entry, replacing whatever was in its slot unless that is
+    /// a deeper entry for the same position, such as one a qsearch revisits.
+    pub fn store(&mut self, hash: u64, ply: u16, entry: TtEntry) {
         let index = self.index(hash);
+        if let Some((old_hash, old)) = self.table[index] {
+            if old_hash == hash && old.depth > entry.depth {
+                return;
+            }
+        }
+        let entry = TtEntry {
+            score: entry.score.to_tt(ply),
+            ..entry
+        };
         self.table[index] = Some((hash, entry));
     }
 
@@ -45,6 +63,35 @@ impl TranspositionTable {
     }
 
     fn index(&self, hash: u64) -> usize {
-        (hash as u128 * self.table.len() as u128 >> 64) as u64 as usize
+        ((hash as u128 * self.table.len() as u128) >> 64) as u64 as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(depth: u8) -> TtEntry {
+        TtEntry {
+            best_move: None,
+            depth,
+            score: Score::DRAW,
+            bound: TtBound::Exact,
+        }
+    }
+
+    #[test]
+    fn keeps_deeper_entries() {
+        let mut tt = TranspositionTable::new(1024);
+        tt.store(1, 0, entry(5));
+        tt.store(1, 0, entry(0));
+        assert_eq!(tt.load(1, 0).map(|entry| entry.depth), Some(5));
+        tt.store(1, 0, entry(7));
+        assert_eq!(tt.load(1, 0).map(|entry| entry.depth), Some(7));
+
+        // Small hashes all land in the first slot, and another position always replaces it.
+        assert_eq!(tt.index(1), tt.index(2));
+        tt.store(2, 0, entry(0));
+        assert!(tt.load(1, 0).is_none());
     }
 }
@@ -1,19 +1,40 @@
+use std::any::Any;
 use std::io::prelude::*;
-use std::time::Duration;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use cozy_chess::{Board, Color};
+use cozy_chess::{Board, Color, Move};
 use cozy_chess::util::{parse_uci_move, display_uci_move};
 
 mod engine;
 mod bench;
 
-use engine::{Engine, SearchLimits};
+use engine::{Engine, MateResult, MoveExplanation, Score, SearchAlgorithm, SearchLimits, SearchStats, TreeDumpConfig, TtBound};
+#[cfg(feature = "tune")]
+use engine::SearchParams;
 
 const DEFAULT_HASH: usize = 16;
+const MAX_HASH: usize = 1_048_576;
+const DEFAULT_MOVE_OVERHEAD: u64 = 10;
+const DEFAULT_MATE_NODES: usize = 1_000_000;
+const DEFAULT_TREE_DUMP_PLY: u16 = 4;
+const DEFAULT_TREE_DUMP_NODES: usize = 100_000;
+const MAX_EXPLAIN_DEPTH: u8 = 10;
 
 fn main() {
     if std::env::args().nth(1).as_deref() == Some("bench") {
-        bench::run_bench();
+        let depth = std::env::args()
+            .nth(2)
+            .map_or(bench::DEFAULT_BENCH_DEPTH, |d| d.parse().expect("failed to parse bench depth"));
+        bench::run_bench(depth);
+        return;
+    }
+
+    #[cfg(feature = "tune")]
+    if std::env::args().nth(1).as_deref() == Some("spsa") {
+        SearchParams::print_spsa_inputs();
         return;
     }
 
@@ -21,6 +42,11 @@ fn main() {
     let mut current_pos = Board::startpos();
     let mut moves_played = Vec::new();
     let mut engine = Engine::new(DEFAULT_HASH * 1024 * 1024);
+    engine.set_move_overhead(Duration::from_millis(DEFAULT_MOVE_OVERHEAD));
+    let mut tree_dump_file = None;
+    let mut tree_dump_ply = DEFAULT_TREE_DUMP_PLY;
+    let mut tree_dump_nodes = DEFAULT_TREE_DUMP_NODES;
+    let mut debug = false;
 
     for line in std::io::stdin().lines() {
         let line = line.expect("failed to read line");
@@ -34,19 +60,65 @@ fn main() {
             "uci" => {
                 println!("id name Minuette 1.0-dev");
                 println!("id author analog hors");
-                println!("option name Hash type spin default {} min 1 max 1048576", DEFAULT_HASH);
+                println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH, MAX_HASH);
+                println!("option name Move Overhead type spin default {} min 0 max 5000", DEFAULT_MOVE_OVERHEAD);
+                println!("option name Tree Dump File type string default <empty>");
+                println!("option name Tree Dump Ply type spin default {} min 0 max 128", DEFAULT_TREE_DUMP_PLY);
+                println!("option name Tree Dump Nodes type spin default {} min 1 max 100000000", DEFAULT_TREE_DUMP_NODES);
+                println!("option name SearchAlgorithm type combo default AlphaBeta var AlphaBeta var MCTS");
+                #[cfg(feature = "tune")]
+                SearchParams::print_uci_options();
                 println!("uciok");
             }
             "setoption" => {
-                let name = get_str_field(&tokens, "name").expect("missing name field");
-                let value = get_str_field(&tokens, "value").expect("missing value field");
-                match name {
+                let (Some(name), Some(value)) = (get_option_name(&tokens), get_option_value(&tokens)) else {
+                    println!("info string setoption needs a name and a value");
+                    flush_stdout();
+                    continue;
+                };
+                match name.as_str() {
                     "Hash" => {
-                        let value = value.parse::<usize>().expect("failed to parse hash value");
-                        engine.resize_tt(value * 1024 * 1024);
+                        if let Some(value) = parse_option::<usize>(&name, &value) {
+                            engine.resize_tt(value.clamp(1, MAX_HASH) * 1024 * 1024);
+                        }
+                    }
+                    "Move Overhead" => {
+                        if let Some(value) = parse_option::<u64>(&name, &value) {
+                            engine.set_move_overhead(Duration::from_millis(value));
+                        }
+                    }
+                    "SearchAlgorithm" => {
+                        match value.as_str() {
+                            "AlphaBeta" => engine.set_search_algorithm(SearchAlgorithm::AlphaBeta),
+                            "MCTS" => engine.set_search_algorithm(SearchAlgorithm::Mcts),
+                            _ => println!("info string unknown search algorithm {}", value),
+                        }
+                    }
+                    "Tree Dump File" => {
+                        tree_dump_file = match value.as_str() {
+                            "" | "<empty>" => None,
+                            path => Some(PathBuf::from(path)),
+                        };
+                    }
+                    "Tree Dump Ply" => {
+                        tree_dump_ply = parse_option(&name, &value).unwrap_or(tree_dump_ply);
+                    }
+                    "Tree Dump Nodes" => {
+                        tree_dump_nodes = parse_option(&name, &value).unwrap_or(tree_dump_nodes);
+                    }
+                    _ => {
+                        #[cfg(feature = "tune")]
+                        if let Ok(value) = value.parse() {
+                            engine.set_param(&name, value);
+                        }
                     }
-                    _ => {}
                 }
+                engine.set_tree_dump(tree_dump_file.clone().map(|path| {
+                    TreeDumpConfig::new(path, tree_dump_ply, tree_dump_nodes)
+                }));
+            }
+            "debug" => {
+                debug = tokens.get(1) == Some(&"on");
             }
             "ucinewgame" => {
                 engine.reset();
@@ -55,24 +127,61 @@ fn main() {
                 println!("readyok");
             }
             "position" => {
-                init_pos = match get_fen(&tokens) {
-                    Some(fen) => fen.parse().expect("failed to parse fen"),
-                    None => Board::startpos(),
-                };
-
-                current_pos = init_pos.clone();
-                moves_played.clear();
-                if let Some(tokens) = get_moves(&tokens) {
-                    for token in tokens {
-                        let mv = parse_uci_move(&current_pos, token)
-                            .expect("failed to parse move");
-                        
-                        current_pos.play(mv);
-                        moves_played.push(mv);
+                // A bad position is reported and ignored, leaving the previous one in place.
+                match parse_position(&tokens) {
+                    Ok((init, current, moves)) => {
+                        init_pos = init;
+                        current_pos = current;
+                        moves_played = moves;
                     }
+                    Err(error) => println!("info string {}", error),
                 }
             }
             "go" => {
+                // Checkmate and stalemate are valid positions to be asked about, but there is no move to search for.
+                if !has_legal_moves(&current_pos) {
+                    println!("bestmove 0000");
+                    flush_stdout();
+                    continue;
+                }
+
+                if let Some(moves) = get_clock_field(&tokens, "mate") {
+                    let start = Instant::now();
+                    let max_nodes = get_clock_field(&tokens, "nodes").map_or(DEFAULT_MATE_NODES, |nodes| nodes as usize);
+                    let mate = panic::catch_unwind(AssertUnwindSafe(|| {
+                        engine.find_mate(&init_pos, &moves_played, moves as u16, max_nodes)
+                    }));
+                    let mate = match mate {
+                        Ok(mate) => mate,
+                        Err(payload) => {
+                            let message = panic_message(&*payload);
+                            println!("info string mate search failed: {}", message);
+                            eprintln!("mate search failed: {}", message);
+                            engine.reset();
+                            let best_move = fallback_move(&mut engine, &init_pos, &moves_played, &current_pos);
+                            print_best_move(&current_pos, best_move);
+                            flush_stdout();
+                            continue;
+                        }
+                    };
+                    match mate {
+                        MateResult::Mate(line) => {
+                            println!(
+                                "info depth {} score mate {} time {} pv {}",
+                                line.len(),
+                                line.len().div_ceil(2),
+                                start.elapsed().as_millis(),
+                                display_line(&current_pos, &line),
+                            );
+                            println!("bestmove {}", display_uci_move(&current_pos, line[0]));
+                            flush_stdout();
+                            continue;
+                        }
+                        MateResult::NoMate => println!("info string no mate in {}", moves),
+                        MateResult::Unknown => println!("info string mate search ran out of nodes"),
+                    }
+                }
+
                 let wtime = get_clock_field(&tokens, "wtime").unwrap_or_default();
                 let btime = get_clock_field(&tokens, "btime").unwrap_or_default();
                 let winc = get_clock_field(&tokens, "winc").unwrap_or_default();
@@ -86,24 +195,63 @@ fn main() {
                 let limits = SearchLimits::PerGame {
                     clock: Duration::from_millis(time as u64),
                     increment: Duration::from_millis(inc as u64),
+                    moves_to_go: get_clock_field(&tokens, "movestogo"),
                 };
 
+                let explain = tokens.contains(&"explain");
                 let mut best_move = None;
-                engine.think(&init_pos, &moves_played, limits, &mut |info| {
-                    best_move = Some(info.best_move);
-                    println!(
-                        "info depth {} nodes {} score cp {} time {} nps {} pv {}",
-                        info.depth,
-                        info.nodes,
-                        info.eval,
-                        info.time.as_millis(),
-                        (info.nodes as f32 / info.time.as_secs_f32()) as u64,
-                        display_uci_move(&current_pos, info.best_move),
-                    );
-                });
-
-                let best_move = best_move.expect("missing best move?");
-                println!("bestmove {}", display_uci_move(&current_pos, best_move));
+                let mut depth = 0;
+                let mut stats = SearchStats::default();
+                // A bug in the search shouldn't forfeit the game, so a panic falls back
+                // to the last completed iteration, or failing that to any sensible move.
+                let search = panic::catch_unwind(AssertUnwindSafe(|| {
+                    engine.think(&init_pos, &moves_played, limits, &mut |info| {
+                        best_move = Some(info.best_move);
+                        depth = info.depth;
+                        stats = info.stats;
+                        println!(
+                            "info depth {} nodes {} score {} time {} nps {} pv {}",
+                            info.depth,
+                            info.nodes,
+                            display_score(info.eval),
+                            info.time.as_millis(),
+                            (info.nodes as f32 / info.time.as_secs_f32()) as u64,
+                            display_line(&current_pos, &info.pv),
+                        );
+                        if debug {
+                            for root_move in &info.root_moves {
+                                println!(
+                                    "info string {} score {}{} nodes {} prevrank {}",
+                                    display_uci_move(&current_pos, root_move.mv),
+                                    display_score(root_move.score),
+                                    if root_move.exact { "" } else { " upperbound" },
+                                    root_move.nodes,
+                                    root_move.prev_rank + 1,
+                                );
+                            }
+                        }
+                    });
+
+                    if explain {
+                        let explain_depth = depth.saturating_sub(1).clamp(1, MAX_EXPLAIN_DEPTH);
+                        for explanation in engine.explain(&init_pos, &moves_played, explain_depth) {
+                            println!("info string explain {}", display_explanation(&current_pos, &explanation));
+                        }
+                    }
+                }));
+                if let Err(payload) = search {
+                    let message = panic_message(&*payload);
+                    println!("info string search failed: {}", message);
+                    eprintln!("search failed: {}", message);
+                    engine.reset();
+                    best_move = best_move.or_else(|| fallback_move(&mut engine, &init_pos, &moves_played, &current_pos));
+                }
+
+                if SearchStats::ENABLED {
+                    println!("info string {}", stats);
+                }
+
+                print_best_move(&current_pos, best_move);
             }
             "quit" => {
                 break;
@@ -116,9 +264,109 @@ fn main() {
     }
 }
 
+/// Formats a score for UCI, as mate in moves rather than plies where there is one.
+fn display_score(score: Score) -> String {
+    match score.mate_distance() {
+        Some(plies) if plies > 0 => format!("mate {}", (plies + 1) / 2),
+        Some(plies) => format!("mate {}", plies / 2),
+        None => format!("cp {}", score.get()),
+    }
+}
+
+/// A move to play after the search fails: the result of a depth 1 search,
+/// or any legal move if even that fails. `None` if there are no legal moves.
+fn fallback_move(engine: &mut Engine, init_pos: &Board, moves_played: &[Move], current_pos: &Board) -> Option<Move> {
+    let mut best_move = None;
+    let limits = SearchLimits::PerMove { depth: 1 };
+    let search = panic::catch_unwind(AssertUnwindSafe(|| engine.think(init_pos, moves_played, limits, &mut |info| {
+        best_move = Some(info.best_move);
+    })));
+    if let Err(payload) = search {
+        eprintln!("fallback search failed: {}", panic_message(&*payload));
+        engine.reset();
+    }
+
+    best_move.or_else(|| {
+        let mut legal_move = None;
+        current_pos.generate_moves(|moves| {
+            legal_move = moves.into_iter().next();
+            legal_move.is_some()
+        });
+        legal_move
+    })
+}
+
+fn has_legal_moves(board: &Board) -> bool {
+    board.generate_moves(|moves| !moves.is_empty())
+}
+
+fn print_best_move(board: &Board, best_move: Option<Move>) {
+    match best_move {
+        Some(best_move) => println!("bestmove {}", display_uci_move(board, best_move)),
+        None => println!("bestmove 0000"),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+    }
+}
+
+/// Formats a move's score and refutation line for `go explain`.
+fn display_explanation(board: &Board, explanation: &MoveExplanation) -> String {
+    let bound = match explanation.bound {
+        TtBound::Exact => "",
+        TtBound::Lower => " lowerbound",
+        TtBound::Upper => " upperbound",
+    };
+    let mut child = board.clone();
+    child.play(explanation.mv);
+    format!(
+        "{} score {}{} refutation {}",
+        display_uci_move(board, explanation.mv),
+        display_score(explanation.score),
+        bound,
+        display_line(&child, &explanation.refutation),
+    )
+}
+
+fn display_line(board: &Board, line: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut moves = Vec::new();
+    for &mv in line {
+        moves.push(display_uci_move(&board, mv).to_string());
+        board.play(mv);
+    }
+    moves.join(" ")
+}
+
+/// Parses a `position` command into the initial position, the current position and the moves between them.
+fn parse_position(tokens: &[&str]) -> Result<(Board, Board, Vec<Move>), String> {
+    let init_pos = match get_fen(tokens) {
+        Some(fen) => fen.parse::<Board>().map_err(|_| format!("invalid fen {}", fen))?,
+        None => Board::startpos(),
+    };
+
+    let mut current_pos = init_pos.clone();
+    let mut moves_played = Vec::new();
+    for token in get_moves(tokens).unwrap_or_default() {
+        let mv = parse_uci_move(&current_pos, token)
+            .ok()
+            .filter(|&mv| current_pos.is_legal(mv))
+            .ok_or_else(|| format!("illegal move {}", token))?;
+        current_pos.play_unchecked(mv);
+        moves_played.push(mv);
+    }
+    Ok((init_pos, current_pos, moves_played))
+}
+
+/// Everything between `fen` and `moves`.
 fn get_fen(tokens: &[&str]) -> Option<String> {
     let fen_index = tokens.iter().position(|&t| t == "fen")? + 1;
-    Some(tokens[fen_index..fen_index + 6].join(" "))
+    let moves_index = tokens.iter().position(|&t| t == "moves").unwrap_or(tokens.len());
+    Some(tokens.get(fen_index..moves_index)?.join(" "))
 }
 
 fn get_moves<'s, 't>(tokens: &'s [&'t str]) -> Option<&'s [&'t str]> {
@@ -126,14 +374,35 @@ fn get_moves<'s, 't>(tokens: &'s [&'t str]) -> Option<&'s [&'t str]> {
     Some(&tokens[moves_index..])
 }
 
-fn get_str_field<'s, 't>(tokens: &'s [&'t str], field: &str) -> Option<&'t str> {
-    let field_index = tokens.iter().position(|&t| t == field)? + 1;
-    Some(&tokens[field_index])
+fn get_option_name(tokens: &[&str]) -> Option<String> {
+    let name_index = tokens.iter().position(|&t| t == "name")? + 1;
+    let value_index = tokens.iter().position(|&t| t == "value").unwrap_or(tokens.len());
+    Some(tokens.get(name_index..value_index)?.join(" "))
+}
+
+/// Everything after `value`, which may be empty or contain spaces for string options.
+fn get_option_value(tokens: &[&str]) -> Option<String> {
+    let value_index = tokens.iter().position(|&t| t == "value")? + 1;
+    Some(tokens[value_index..].join(" "))
 }
 
+/// A numeric `go` field. A missing or malformed value is reported and treated as absent.
 fn get_clock_field(tokens: &[&str], field: &str) -> Option<u32> {
     let field_index = tokens.iter().position(|&t| t == field)? + 1;
-    Some(tokens[field_index].parse().expect("failed to parse int"))
+    let value = tokens.get(field_index).and_then(|value| value.parse().ok());
+    if value.is_none() {
+        println!("info string invalid value for {}", field);
+    }
+    value
+}
+
+/// Parses an option value, reporting a malformed one.
+fn parse_option<T: FromStr>(name: &str, value: &str) -> Option<T> {
+    let value = value.parse().ok();
+    if value.is_none() {
+        println!("info string invalid value for {}", name);
+    }
+    value
 }
 
 fn flush_stdout() {